@@ -0,0 +1,273 @@
+//! Coordinated linear moves across several STEP/DIR axes.
+//!
+//! A `CoordinatedMove` drives N axes (e.g. X/Y/Z, each with its own TMC2160) along a straight line.
+//! The axis with the longest distance (the major axis) follows a trapezoidal `Motion` profile; the
+//! other axes are stepped with a Bresenham-style error accumulator on each major-axis step. All
+//! axes therefore start and finish together and share the same acceleration profile.
+//!
+//! Velocity and acceleration limits are given in steps of the major axis.
+//!
+//! Backlash compensation steps pending on the axes after `prepare` (see `Tmc2160::set_backlash`)
+//! are scheduled by the move itself: they are issued as separate `CoordinatedStep`s with
+//! `backlash` set before the first step of the profile, spaced by the longest compensation step
+//! interval of the axes involved. `apply` therefore never blocks on compensation and the axes stay
+//! synchronized.
+
+use crate::motion::{Motion, StepDir};
+use crate::types::Direction;
+use embedded_hal::delay::DelayNs;
+
+/// One step of a coordinated move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoordinatedStep<const N: usize> {
+    /// Time to wait before issuing the step, in nanoseconds.
+    pub delay_ns: u32,
+    /// Axes that step at this time.
+    pub axes: [bool; N],
+    /// Whether the steps are uncounted backlash compensation steps.
+    pub backlash: bool,
+}
+
+/// Straight-line move of N synchronized axes.
+pub struct CoordinatedMove<'a, E, const N: usize> {
+    axes: [&'a mut dyn StepDir<Error = E>; N],
+    /// Distance of each axis in steps.
+    deltas: [u64; N],
+    /// Direction of each axis.
+    directions: [Direction; N],
+    /// Bresenham error accumulator of each axis.
+    accumulators: [u64; N],
+    /// Backlash compensation steps still to be issued on each axis.
+    backlash: [u32; N],
+    /// Delay before the next step of the profile, at least the compensation step interval after
+    /// the last compensation step.
+    min_delay_ns: u32,
+    /// Distance of the major axis in steps.
+    major: u64,
+    /// Profile of the major axis.
+    profile: Motion,
+}
+
+impl<'a, E, const N: usize> CoordinatedMove<'a, E, N> {
+    /// Plans a move of all axes to the given absolute target positions.
+    ///
+    /// Positions are in steps of each axis, as reported by `StepDir::position`. `max_velocity`
+    /// (steps per second) and `acceleration` (steps per second squared) apply to the major axis.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_velocity` or `acceleration` is not positive.
+    pub fn new(
+        axes: [&'a mut dyn StepDir<Error = E>; N],
+        targets: [i64; N],
+        max_velocity: f32,
+        acceleration: f32,
+    ) -> Self {
+        let mut deltas = [0u64; N];
+        let mut directions = [Direction::CW; N];
+        for (i, axis) in axes.iter().enumerate() {
+            let delta = targets[i] - axis.position();
+            deltas[i] = delta.unsigned_abs();
+            if delta < 0 {
+                directions[i] = Direction::CCW;
+            }
+        }
+        let major = deltas.iter().copied().max().unwrap_or(0);
+        let mut profile = Motion::new(max_velocity, acceleration);
+        profile.move_to(major as i64);
+        Self {
+            axes,
+            deltas,
+            directions,
+            // Start half-way so minor axis steps are centred between major axis steps.
+            accumulators: [major / 2; N],
+            backlash: [0; N],
+            min_delay_ns: 0,
+            major,
+            profile,
+        }
+    }
+
+    /// Returns the distance of the major axis in steps.
+    pub fn major_steps(&self) -> u64 {
+        self.major
+    }
+
+    /// Sets the DIR pin of every moving axis and takes over their pending backlash compensation
+    /// steps. Must be called before the first step.
+    ///
+    /// Axes that do not move keep their direction, so they do not wait for the DIR hold and setup
+    /// times of a reversal they never step in, and keep their compensation for their next move.
+    pub fn prepare(&mut self) -> Result<(), E> {
+        for (i, axis) in self.axes.iter_mut().enumerate() {
+            if self.deltas[i] != 0 {
+                axis.set_direction(self.directions[i])?;
+                self.backlash[i] = axis.pending_backlash();
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the next step of the move without driving any pins.
+    ///
+    /// This is intended for timer-driven execution: wait `delay_ns`, then call `apply`. Returns
+    /// `None` once all axes have reached their targets.
+    pub fn next_step(&mut self) -> Option<CoordinatedStep<N>> {
+        if self.backlash.iter().any(|&steps| steps > 0) {
+            let mut axes = [false; N];
+            let mut interval = 0;
+            for (i, step) in axes.iter_mut().enumerate() {
+                if self.backlash[i] > 0 {
+                    self.backlash[i] -= 1;
+                    *step = true;
+                    interval = interval.max(self.axes[i].backlash_interval_ns());
+                }
+            }
+            let delay_ns = self.min_delay_ns;
+            self.min_delay_ns = interval;
+            return Some(CoordinatedStep {
+                delay_ns,
+                axes,
+                backlash: true,
+            });
+        }
+        let interval = self.profile.next_step_interval()?;
+        let mut axes = [false; N];
+        for (i, step) in axes.iter_mut().enumerate() {
+            self.accumulators[i] += self.deltas[i];
+            if self.accumulators[i] >= self.major {
+                self.accumulators[i] -= self.major;
+                *step = true;
+            }
+        }
+        let delay_ns = interval.delay_ns.max(self.min_delay_ns);
+        self.min_delay_ns = 0;
+        Some(CoordinatedStep {
+            delay_ns,
+            axes,
+            backlash: false,
+        })
+    }
+
+    /// Issues a step on every axis selected in `step`, without blocking.
+    pub fn apply(&mut self, step: &CoordinatedStep<N>) -> Result<(), E> {
+        for (axis, &selected) in self.axes.iter_mut().zip(step.axes.iter()) {
+            if !selected {
+                continue;
+            }
+            if step.backlash {
+                axis.backlash_step()?;
+            } else {
+                axis.step()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes the whole move, blocking until all axes have reached their targets.
+    pub fn run<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), E> {
+        self.prepare()?;
+        while let Some(step) = self.next_step() {
+            delay.delay_ns(step.delay_ns);
+            self.apply(&step)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::{Bench, Clock, Delay};
+    use crate::types::Backlash;
+    use std::vec::Vec;
+
+    #[test]
+    fn axes_finish_together() {
+        let clock = Clock::default();
+        let mut x = Bench::with_clock(&clock);
+        let mut y = Bench::with_clock(&clock);
+        let mut line =
+            CoordinatedMove::new([&mut x.driver, &mut y.driver], [12, -4], 1_000.0, 10_000.0);
+        assert_eq!(line.major_steps(), 12);
+        line.prepare().unwrap();
+        let mut counts = [0; 2];
+        while let Some(step) = line.next_step() {
+            for (count, &selected) in counts.iter_mut().zip(&step.axes) {
+                *count += selected as u32;
+            }
+            line.apply(&step).unwrap();
+        }
+        assert_eq!(counts, [12, 4]);
+        assert_eq!((x.driver.position(), y.driver.position()), (12, -4));
+    }
+
+    #[test]
+    fn idle_axis_keeps_direction() {
+        let clock = Clock::default();
+        let mut x = Bench::with_clock(&clock);
+        let mut y = Bench::with_clock(&clock);
+        y.driver.set_direction(Direction::CCW).unwrap();
+        let mut delay = Delay(clock.clone());
+        CoordinatedMove::new([&mut x.driver, &mut y.driver], [5, 0], 1_000.0, 10_000.0)
+            .run(&mut delay)
+            .unwrap();
+        assert_eq!(y.driver.direction(), Direction::CCW);
+        assert_eq!(y.dir_changes().len(), 1);
+        assert!(y.step_edges(false).is_empty());
+        assert_eq!(x.driver.position(), 5);
+    }
+
+    #[test]
+    fn backlash_does_not_desync_axes() {
+        let clock = Clock::default();
+        let mut x = Bench::with_clock(&clock);
+        let mut y = Bench::with_clock(&clock);
+        x.driver.set_backlash(Backlash {
+            microsteps: 4,
+            step_interval_ns: 20_000,
+        });
+        let mut delay = Delay(clock.clone());
+        CoordinatedMove::new([&mut x.driver, &mut y.driver], [10, 10], 1_000.0, 10_000.0)
+            .run(&mut delay)
+            .unwrap();
+        let start = clock.now();
+
+        // X reverses and takes up its backlash before the move; Y keeps its direction.
+        let mut line =
+            CoordinatedMove::new([&mut x.driver, &mut y.driver], [0, 20], 1_000.0, 10_000.0);
+        line.prepare().unwrap();
+        let mut steps = [0; 2];
+        while let Some(step) = line.next_step() {
+            steps[step.backlash as usize] += 1;
+            assert!(!step.backlash || step.axes == [true, false]);
+            delay.delay_ns(step.delay_ns);
+            line.apply(&step).unwrap();
+        }
+        assert_eq!(steps, [10, 4]);
+        assert_eq!((x.driver.position(), y.driver.position()), (0, 20));
+        assert_eq!(x.driver.pending_backlash(), 0);
+
+        let x_steps: Vec<u64> = x
+            .step_edges(false)
+            .into_iter()
+            .filter(|&t| t > start)
+            .collect();
+        let y_steps: Vec<u64> = y
+            .step_edges(false)
+            .into_iter()
+            .filter(|&t| t > start)
+            .collect();
+        assert_eq!((x_steps.len(), y_steps.len()), (14, 10));
+        // Compensation steps are spaced by their interval.
+        for pair in x_steps[..5].windows(2) {
+            assert!(pair[1] - pair[0] >= 20_000, "{x_steps:?}");
+        }
+        // Counted steps of both axes are issued together, one pulse apart.
+        for (x_step, y_step) in x_steps[4..].iter().zip(&y_steps) {
+            assert!(y_step - x_step <= 200, "{x_steps:?} {y_steps:?}");
+        }
+    }
+}
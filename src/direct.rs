@@ -0,0 +1,146 @@
+//! Direct coil current control through XDIRECT.
+//!
+//! In direct mode (GCONF.direct_mode) the sequencer is bypassed: STEP/DIR are ignored and the coil
+//! currents are taken from the XDIRECT register. The values are signed and scaled like the
+//! sequencer's sine table, i.e. ±255 is full scale; the resulting current is further scaled by
+//! IHOLD and GLOBAL_SCALER. This allows arbitrary current vectors, e.g. for field-oriented control
+//! experiments or to hold a fixed electrical angle.
+//!
+//! A `DirectModeController` borrows the driver for as long as direct mode is active, so no steps
+//! can be issued in the meantime. Since the rotor follows the commanded vector, the tracked
+//! position is marked as uncertain; re-reference it with `Tmc2160::set_position` after leaving
+//! direct mode.
+//!
+//! ```ignore
+//! let mut direct = DirectModeController::enable(&mut driver)?;
+//! direct.set_angle(90.0, 200)?; // Hold at 90° electrical.
+//! direct.disable()?;
+//! ```
+
+use crate::registers::{GConf, Register, XDirect};
+use crate::tmc2160::Tmc2160;
+use crate::types::{Error, InvalidArgument};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Largest coil current magnitude accepted by XDIRECT.
+pub const MAX_COIL_CURRENT: i16 = 255;
+
+/// Exclusive access to a driver in direct mode.
+pub struct DirectModeController<'a, SPI, CS, EN, DIR, STEP, D> {
+    driver: &'a mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+}
+
+impl<'a, SPI, CS, EN, DIR, STEP, D, SpiE, PinE> DirectModeController<'a, SPI, CS, EN, DIR, STEP, D>
+where
+    SPI: SpiBus<u8, Error = SpiE>,
+    CS: OutputPin<Error = PinE>,
+    EN: OutputPin<Error = PinE>,
+    DIR: OutputPin<Error = PinE>,
+    STEP: OutputPin<Error = PinE>,
+    D: DelayNs,
+{
+    /// Enables direct mode with both coil currents at zero.
+    ///
+    /// XDIRECT is cleared before GCONF.direct_mode is set, so the motor is released rather than
+    /// jumping to a stale vector. The tracked position is marked as uncertain.
+    pub fn enable(
+        driver: &'a mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<Self, Error<SpiE, PinE>> {
+        driver.write_register(Register::XDirect, 0)?;
+        driver.modify_register(Register::GConf, |val| {
+            let mut gconf = GConf(val);
+            gconf.set_direct_mode(true);
+            gconf.0
+        })?;
+        driver.mark_position_uncertain();
+        Ok(Self { driver })
+    }
+
+    /// Sets the coil A and coil B currents.
+    ///
+    /// Both values must be within `-MAX_COIL_CURRENT..=MAX_COIL_CURRENT`.
+    pub fn set_currents(&mut self, coil_a: i16, coil_b: i16) -> Result<(), Error<SpiE, PinE>> {
+        let reg = Some(Register::XDirect);
+        let max = MAX_COIL_CURRENT as f32;
+        InvalidArgument::check(reg, "coil_a", coil_a as f32, -max, max)?;
+        InvalidArgument::check(reg, "coil_b", coil_b as f32, -max, max)?;
+        let mut xdirect = XDirect(0);
+        xdirect.set_coil_a(coil_a);
+        xdirect.set_coil_b(coil_b);
+        self.driver.write_register(Register::XDirect, xdirect.0)
+    }
+
+    /// Returns the coil A and coil B currents currently set in XDIRECT.
+    pub fn currents(&mut self) -> Result<(i16, i16), Error<SpiE, PinE>> {
+        let xdirect = XDirect(self.driver.read_register(Register::XDirect)?);
+        Ok((xdirect.coil_a(), xdirect.coil_b()))
+    }
+
+    /// Sets a current vector of the given magnitude at an electrical angle in degrees.
+    ///
+    /// Coil A follows the sine and coil B the cosine of the angle, matching the sequencer, so
+    /// 360° correspond to one electrical cycle (four full steps).
+    pub fn set_angle(&mut self, degrees: f32, magnitude: u8) -> Result<(), Error<SpiE, PinE>> {
+        let radians = degrees.to_radians();
+        let magnitude = magnitude as f32;
+        let coil_a = libm::roundf(magnitude * libm::sinf(radians)) as i16;
+        let coil_b = libm::roundf(magnitude * libm::cosf(radians)) as i16;
+        self.set_currents(coil_a, coil_b)
+    }
+
+    /// Leaves direct mode and returns control of the coils to the sequencer.
+    pub fn disable(self) -> Result<(), Error<SpiE, PinE>> {
+        self.driver.modify_register(Register::GConf, |val| {
+            let mut gconf = GConf(val);
+            gconf.set_direct_mode(false);
+            gconf.0
+        })?;
+        self.driver.write_register(Register::XDirect, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::Bench;
+
+    #[test]
+    fn xdirect_sign_extends_coil_currents() {
+        let xdirect = XDirect(0x1FF | 0x101 << 16);
+        assert_eq!(xdirect.coil_a(), -1);
+        assert_eq!(xdirect.coil_b(), -255);
+
+        let mut xdirect = XDirect(0);
+        xdirect.set_coil_a(-255);
+        xdirect.set_coil_b(100);
+        assert_eq!(xdirect.0, 0x101 | 100 << 16);
+    }
+
+    #[test]
+    fn enable_and_disable_restore_gconf() {
+        let mut bench = Bench::new();
+        let mut gconf = GConf(0);
+        gconf.set_shaft(true);
+        gconf.set_stop_enable(true);
+        bench.spi.set_reg(Register::GConf as u8, gconf.0);
+        bench.spi.set_reg(Register::XDirect as u8, 0x80);
+
+        let mut direct = DirectModeController::enable(&mut bench.driver).unwrap();
+        direct.set_currents(-255, 100).unwrap();
+        assert_eq!(direct.currents().unwrap(), (-255, 100));
+        let direct_gconf = GConf(bench.spi.reg(Register::GConf as u8));
+        assert!(direct_gconf.direct_mode());
+        assert_eq!(direct_gconf.0 & !(1 << 16), gconf.0);
+        assert!(matches!(
+            direct.set_currents(256, 0),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        direct.disable().unwrap();
+        assert_eq!(bench.spi.reg(Register::GConf as u8), gconf.0);
+        assert_eq!(bench.spi.reg(Register::XDirect as u8), 0);
+        assert!(bench.driver.is_position_uncertain());
+    }
+}
@@ -0,0 +1,49 @@
+//! Emergency stop through the DCIN/CFG5 input.
+//!
+//! With GCONF.stop_enable set, the TMC2160 treats a high level on DCIN (ENCA_DCIN_CFG5) as an
+//! emergency stop: the sequencer ignores STEP pulses and the motor is held at standstill. Without
+//! stop_enable, DCIN is the DcStep gating input and does not stop the motor, so it only acts as a
+//! stop input once stop_enable is configured (see `Tmc2160::set_stop_enable`). Do not confuse it
+//! with DCEN (ENCB_DCEN_CFG4), which enables DcStep.
+//!
+//! The pin may be driven by an external e-stop circuit, or by the MCU through a `StopInput`. In both
+//! cases `Tmc2160::emergency_stop` and `Tmc2160::release` freeze and unfreeze the software motion
+//! engine; `Tmc2160::poll_stop_input` detects a stop asserted by external hardware.
+//!
+//! Steps issued while the stop is active are lost, and a stop may interrupt a step or a backlash
+//! compensation sequence, so the tracked position is marked as uncertain after every emergency
+//! stop. It becomes certain again when the axis is re-referenced with `Tmc2160::set_position` (or a
+//! homing routine).
+
+use embedded_hal::digital::OutputPin;
+
+/// The DCIN/CFG5 pin of the TMC2160, driven by the MCU as emergency stop input.
+///
+/// The MCU output must be wired to DCIN, not to DCEN.
+#[derive(Debug)]
+pub struct StopInput<P> {
+    pin: P,
+}
+
+impl<P: OutputPin> StopInput<P> {
+    /// Creates a stop input on `pin` and drives it low (stop inactive).
+    pub fn new(mut pin: P) -> Result<Self, P::Error> {
+        pin.set_low()?;
+        Ok(Self { pin })
+    }
+
+    /// Drives DCIN high, stopping the sequencer.
+    pub(crate) fn assert(&mut self) -> Result<(), P::Error> {
+        self.pin.set_high()
+    }
+
+    /// Drives DCIN low.
+    pub(crate) fn deassert(&mut self) -> Result<(), P::Error> {
+        self.pin.set_low()
+    }
+
+    /// Releases the pin.
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
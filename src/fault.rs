@@ -0,0 +1,446 @@
+//! Fault monitoring through the DIAG0 and DIAG1 outputs.
+//!
+//! The TMC2160 signals driver errors and warnings on its SWN_DIAG0 and SWP_DIAG1 pins, as selected
+//! by the GCONF diag0_*/diag1_* bits. `FaultMonitor::configure` routes errors (overtemperature,
+//! short circuits, charge pump undervoltage) and the overtemperature prewarning to DIAG0, and
+//! StallGuard stalls to DIAG1. Both outputs are open drain (active low) by default; with
+//! `ActiveLevel::High` they are switched to push-pull (active high).
+//!
+//! When a DIAG input is active, GSTAT and DRV_STATUS are read and the cause is classified into a
+//! `FaultEvent`. Each event is reported once while the inputs stay active; a different event, or
+//! the same one after the inputs were released, is reported again. GSTAT.reset is latched, so it is
+//! cleared once `FaultEvent::Reset` has been reported; the other flags stay set while their cause
+//! persists. `FaultEvent::action` gives the recommended reaction. The monitor only reports; stopping motion and acknowledging the fault
+//! (`Tmc2160::acknowledge_faults`) is up to the application.
+//!
+//! The inputs can be polled as `InputPin`s, or awaited as `Wait` inputs with the `async` feature.
+//!
+//! ```ignore
+//! let mut faults = FaultMonitor::new(diag0, diag1, ActiveLevel::Low);
+//! faults.configure(&mut driver)?;
+//! // Periodically, e.g. from the main loop:
+//! if let Some(event) = faults.poll(&mut driver)? {
+//!     handle(event, event.action());
+//! }
+//! ```
+
+use crate::limits::ActiveLevel;
+use crate::registers::{DrvStatus, GConf, GStat, Register};
+use crate::tmc2160::Tmc2160;
+use crate::types::Error;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiBus;
+
+/// A fault or warning reported by the driver.
+///
+/// When several causes are flagged at once, the most severe one is reported, in the order of the
+/// variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultEvent {
+    /// The driver was reset (GSTAT.reset), e.g. after a supply dropout; its configuration is lost.
+    Reset,
+    /// Short to ground on a low side MOSFET; the bridges are disabled.
+    ShortToGround {
+        /// Short detected on coil A.
+        coil_a: bool,
+        /// Short detected on coil B.
+        coil_b: bool,
+    },
+    /// Short to supply on a high side MOSFET; the bridges are disabled.
+    ShortToSupply {
+        /// Short detected on coil A.
+        coil_a: bool,
+        /// Short detected on coil B.
+        coil_b: bool,
+    },
+    /// Overtemperature shutdown; the bridges are disabled until the driver has cooled down.
+    Overtemperature,
+    /// Charge pump undervoltage (GSTAT.uv_cp); the driver is disabled while it persists.
+    ChargePumpUndervoltage,
+    /// The overtemperature prewarning threshold was exceeded.
+    OvertemperatureWarning,
+    /// StallGuard detected a motor stall.
+    Stall,
+}
+
+/// Recommended reaction to a `FaultEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Run `Tmc2160::init` again and restore the configuration, then re-reference the position.
+    Reinitialize,
+    /// Stop motion and check the motor and its wiring, then call `Tmc2160::acknowledge_faults`.
+    InspectWiring,
+    /// Stop motion and let the driver cool down, then call `Tmc2160::acknowledge_faults`.
+    CoolDown,
+    /// Stop motion and check the supply voltage; the driver resumes once the charge pump recovers.
+    CheckSupply,
+    /// Reduce the motor current or duty cycle to avoid an overtemperature shutdown.
+    ReduceCurrent,
+    /// Stop motion and re-reference the position, which is lost after a stall.
+    Rehome,
+}
+
+impl FaultEvent {
+    /// Classifies the flags of GSTAT and DRV_STATUS.
+    ///
+    /// Returns `None` if no fault or warning is flagged.
+    pub fn classify(gstat: GStat, drv_status: DrvStatus) -> Option<Self> {
+        let event = if gstat.reset() {
+            FaultEvent::Reset
+        } else if drv_status.s2ga() || drv_status.s2gb() {
+            FaultEvent::ShortToGround {
+                coil_a: drv_status.s2ga(),
+                coil_b: drv_status.s2gb(),
+            }
+        } else if drv_status.s2vsa() || drv_status.s2vsb() {
+            FaultEvent::ShortToSupply {
+                coil_a: drv_status.s2vsa(),
+                coil_b: drv_status.s2vsb(),
+            }
+        } else if drv_status.ot() {
+            FaultEvent::Overtemperature
+        } else if gstat.uv_cp() {
+            FaultEvent::ChargePumpUndervoltage
+        } else if drv_status.otpw() {
+            FaultEvent::OvertemperatureWarning
+        } else if drv_status.stall_guard() {
+            FaultEvent::Stall
+        } else {
+            return None;
+        };
+        Some(event)
+    }
+
+    /// Returns the recommended reaction to this event.
+    pub fn action(&self) -> FaultAction {
+        match self {
+            FaultEvent::Reset => FaultAction::Reinitialize,
+            FaultEvent::ShortToGround { .. } | FaultEvent::ShortToSupply { .. } => {
+                FaultAction::InspectWiring
+            }
+            FaultEvent::Overtemperature => FaultAction::CoolDown,
+            FaultEvent::ChargePumpUndervoltage => FaultAction::CheckSupply,
+            FaultEvent::OvertemperatureWarning => FaultAction::ReduceCurrent,
+            FaultEvent::Stall => FaultAction::Rehome,
+        }
+    }
+
+    /// Returns `true` if the driver has disabled its bridges because of this event.
+    pub fn is_shutdown(&self) -> bool {
+        matches!(
+            self,
+            FaultEvent::ShortToGround { .. }
+                | FaultEvent::ShortToSupply { .. }
+                | FaultEvent::Overtemperature
+                | FaultEvent::ChargePumpUndervoltage
+        )
+    }
+}
+
+/// Watches the DIAG0 and DIAG1 outputs and classifies the faults they signal.
+#[derive(Debug)]
+pub struct FaultMonitor<DIAG0, DIAG1> {
+    diag0: DIAG0,
+    diag1: DIAG1,
+    /// Level at which the DIAG outputs are active.
+    active: ActiveLevel,
+    /// Event reported while the inputs have been active, if any.
+    reported: Option<FaultEvent>,
+}
+
+impl<DIAG0, DIAG1> FaultMonitor<DIAG0, DIAG1> {
+    /// Creates a monitor on the DIAG0 and DIAG1 inputs.
+    ///
+    /// `active` selects the output mode set by `configure`: `ActiveLevel::Low` for the default
+    /// open drain outputs (an external or internal pull-up is required), `ActiveLevel::High` for
+    /// push-pull outputs.
+    pub fn new(diag0: DIAG0, diag1: DIAG1, active: ActiveLevel) -> Self {
+        Self {
+            diag0,
+            diag1,
+            active,
+            reported: None,
+        }
+    }
+
+    /// Returns the event reported while the inputs have been active, if any.
+    pub fn reported(&self) -> Option<FaultEvent> {
+        self.reported
+    }
+
+    /// Releases the DIAG input pins.
+    pub fn release(self) -> (DIAG0, DIAG1) {
+        (self.diag0, self.diag1)
+    }
+
+    /// Configures the DIAG outputs in GCONF and clears GSTAT.
+    ///
+    /// DIAG0 is set to signal driver errors and the overtemperature prewarning, DIAG1 to signal
+    /// stalls; the other diag0_*/diag1_* options are cleared. Stalls are only signalled above the
+    /// velocity set by TCOOLTHRS. GSTAT is cleared so the reset flag set at power-up is not
+    /// reported.
+    pub fn configure<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<(), Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+    {
+        let push_pull = self.active == ActiveLevel::High;
+        driver.modify_register(Register::GConf, |val| {
+            let mut gconf = GConf(val);
+            gconf.set_diag0_error(true);
+            gconf.set_diag0_otpw(true);
+            gconf.set_diag0_stall(false);
+            gconf.set_diag1_stall(true);
+            gconf.set_diag1_index(false);
+            gconf.set_diag1_onstate(false);
+            gconf.set_diag1_steps_skipped(false);
+            gconf.set_diag0_int_pushpull(push_pull);
+            gconf.set_diag1_pushpull(push_pull);
+            gconf.0
+        })?;
+        driver.clear_global_status()?;
+        self.reported = None;
+        Ok(())
+    }
+
+    /// Reads GSTAT and DRV_STATUS and classifies the flags, see `FaultEvent::classify`.
+    ///
+    /// Unlike `poll`, this neither checks the DIAG inputs nor suppresses repeated events.
+    pub fn classify<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<Option<FaultEvent>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+    {
+        let gstat = GStat(driver.read_register(Register::GStat)?);
+        let drv_status = DrvStatus(driver.read_register(Register::DrvStatus)?);
+        Ok(FaultEvent::classify(gstat, drv_status))
+    }
+
+    /// Checks the DIAG inputs and classifies an active fault.
+    ///
+    /// Returns `Some(event)` when a fault is signalled that has not been reported since the inputs
+    /// became active, and `None` otherwise. The status registers are only read while an input is
+    /// active. After `FaultEvent::Reset` is reported, GSTAT.reset is cleared, so a cause flagged
+    /// at the same time is reported by the next poll.
+    pub fn poll<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<Option<FaultEvent>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        DIAG0: InputPin<Error = PinE>,
+        DIAG1: InputPin<Error = PinE>,
+    {
+        if !self.is_signalled().map_err(Error::Pin)? {
+            self.reported = None;
+            return Ok(None);
+        }
+        let event = Self::classify(driver)?;
+        if event.is_none() || event == self.reported {
+            return Ok(None);
+        }
+        if event == Some(FaultEvent::Reset) {
+            let mut reset = GStat(0);
+            reset.set_reset(true);
+            driver.write_register(Register::GStat, reset.0)?;
+        }
+        self.reported = event;
+        Ok(event)
+    }
+
+    /// Returns `true` if either DIAG input is active.
+    fn is_signalled<PinE>(&mut self) -> Result<bool, PinE>
+    where
+        DIAG0: InputPin<Error = PinE>,
+        DIAG1: InputPin<Error = PinE>,
+    {
+        Ok(match self.active {
+            ActiveLevel::High => self.diag0.is_high()? || self.diag1.is_high()?,
+            ActiveLevel::Low => self.diag0.is_low()? || self.diag1.is_low()?,
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl<DIAG0, DIAG1> FaultMonitor<DIAG0, DIAG1> {
+    /// Waits until a fault is signalled and returns it.
+    ///
+    /// A fault that is already signalled but not yet reported is returned immediately. Otherwise
+    /// this waits for either DIAG input to become active and classifies the fault, like `poll`.
+    pub async fn wait<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<FaultEvent, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        DIAG0: InputPin<Error = PinE> + embedded_hal_async::digital::Wait,
+        DIAG1: InputPin<Error = PinE> + embedded_hal_async::digital::Wait,
+    {
+        loop {
+            if let Some(event) = self.poll(driver)? {
+                return Ok(event);
+            }
+            self.wait_for_edge().await.map_err(Error::Pin)?;
+            // A new activation is reported even if it has the same cause as the previous one.
+            self.reported = None;
+        }
+    }
+
+    /// Waits for an edge to the active level on either DIAG input.
+    async fn wait_for_edge<PinE>(&mut self) -> Result<(), PinE>
+    where
+        DIAG0: embedded_hal_async::digital::Wait<Error = PinE>,
+        DIAG1: embedded_hal_async::digital::Wait<Error = PinE>,
+    {
+        use core::future::{poll_fn, Future};
+        use core::pin::pin;
+        use core::task::Poll;
+
+        let Self {
+            diag0,
+            diag1,
+            active,
+            ..
+        } = self;
+        let mut diag0 = pin!(async {
+            match active {
+                ActiveLevel::High => diag0.wait_for_rising_edge().await,
+                ActiveLevel::Low => diag0.wait_for_falling_edge().await,
+            }
+        });
+        let mut diag1 = pin!(async {
+            match active {
+                ActiveLevel::High => diag1.wait_for_rising_edge().await,
+                ActiveLevel::Low => diag1.wait_for_falling_edge().await,
+            }
+        });
+        poll_fn(|cx| match diag0.as_mut().poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result),
+            Poll::Pending => diag1.as_mut().poll(cx),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Bench, Input};
+
+    const RESET: u32 = 1 << 0;
+    const UV_CP: u32 = 1 << 2;
+    const S2VSA: u32 = 1 << 12;
+    const S2VSB: u32 = 1 << 13;
+    const STALL: u32 = 1 << 24;
+    const OT: u32 = 1 << 25;
+    const OTPW: u32 = 1 << 26;
+    const S2GA: u32 = 1 << 27;
+
+    #[test]
+    fn classify_reports_most_severe_cause() {
+        let cases = [
+            (RESET | UV_CP, S2GA | OT, Some(FaultEvent::Reset)),
+            (
+                UV_CP,
+                S2GA | S2VSB | OT,
+                Some(FaultEvent::ShortToGround {
+                    coil_a: true,
+                    coil_b: false,
+                }),
+            ),
+            (
+                0,
+                S2VSA | S2VSB | OT | OTPW,
+                Some(FaultEvent::ShortToSupply {
+                    coil_a: true,
+                    coil_b: true,
+                }),
+            ),
+            (UV_CP, OT | OTPW, Some(FaultEvent::Overtemperature)),
+            (
+                UV_CP,
+                OTPW | STALL,
+                Some(FaultEvent::ChargePumpUndervoltage),
+            ),
+            (0, OTPW | STALL, Some(FaultEvent::OvertemperatureWarning)),
+            (0, STALL, Some(FaultEvent::Stall)),
+            (0, 0, None),
+        ];
+        for (gstat, drv_status, expected) in cases {
+            assert_eq!(
+                FaultEvent::classify(GStat(gstat), DrvStatus(drv_status)),
+                expected,
+                "GSTAT {gstat:#x}, DRV_STATUS {drv_status:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn poll_reports_once_per_activation() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::DrvStatus as u8, OT);
+        // DIAG0 is active low: active twice, released, then active again.
+        let diag0 = Input::new(&[false, false, false, true, false]);
+        let mut faults = FaultMonitor::new(diag0, Input::new(&[true]), ActiveLevel::Low);
+        let driver = &mut bench.driver;
+        assert_eq!(
+            faults.poll(driver).unwrap(),
+            Some(FaultEvent::Overtemperature)
+        );
+        assert_eq!(faults.poll(driver).unwrap(), None);
+        // A different cause while the input stays active is reported.
+        bench.spi.set_reg(Register::DrvStatus as u8, OTPW);
+        let driver = &mut bench.driver;
+        assert_eq!(
+            faults.poll(driver).unwrap(),
+            Some(FaultEvent::OvertemperatureWarning)
+        );
+        assert_eq!(faults.poll(driver).unwrap(), None);
+        assert_eq!(faults.reported(), None);
+        assert_eq!(
+            faults.poll(driver).unwrap(),
+            Some(FaultEvent::OvertemperatureWarning)
+        );
+    }
+
+    #[test]
+    fn poll_clears_reported_reset() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::GStat as u8, RESET | UV_CP);
+        let mut faults =
+            FaultMonitor::new(Input::new(&[false]), Input::new(&[true]), ActiveLevel::Low);
+        let driver = &mut bench.driver;
+        assert_eq!(faults.poll(driver).unwrap(), Some(FaultEvent::Reset));
+        assert_eq!(bench.spi.reg(Register::GStat as u8), UV_CP);
+        let driver = &mut bench.driver;
+        assert_eq!(
+            faults.poll(driver).unwrap(),
+            Some(FaultEvent::ChargePumpUndervoltage)
+        );
+        assert_eq!(faults.poll(driver).unwrap(), None);
+    }
+}
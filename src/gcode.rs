@@ -0,0 +1,626 @@
+//! A small G-code interpreter for bench fixtures.
+//!
+//! This module parses single G-code lines and executes them on up to three axes (X, Y and Z),
+//! each driven by its own TMC2160. The supported subset is:
+//!
+//! | Command | Description                                                   |
+//! |---------|---------------------------------------------------------------|
+//! | G0      | Rapid move (`G0 X10 Y5`) at the configured rapid feed rate    |
+//! | G1      | Linear move (`G1 X10 F600`), feed rate `F` in mm/min          |
+//! | G28     | Return the given axes (or all axes) to the origin, position 0 |
+//! | G90     | Absolute positioning                                          |
+//! | G91     | Relative positioning                                          |
+//! | M17     | Enable the given axes (or all axes)                           |
+//! | M18/M84 | Disable the given axes (or all axes)                          |
+//! | M906    | Set the run current of the given axes in mA (`M906 X800`)     |
+//!
+//! G28 is not a homing cycle. Many G-code dialects home the machine with G28; here it only moves
+//! back to position 0 of the tracked positions. This module provides no homing: establish the
+//! origin before running G-code, e.g. by homing each axis against a switch and resetting its
+//! position.
+//!
+//! Line numbers (`N10`), checksums (`*71`) and comments (`; ...` or `( ... )`) are ignored. The
+//! parser and executor are `no_std` and allocation free, so lines can be streamed over a UART.
+
+use crate::coordinated::CoordinatedMove;
+use crate::motion::StepDir;
+use embedded_hal::delay::DelayNs;
+
+/// Number of axes addressable by G-code words.
+pub const MAX_AXES: usize = 3;
+
+/// Axis letters, in axis order.
+const AXIS_LETTERS: [u8; MAX_AXES] = [b'X', b'Y', b'Z'];
+
+/// Errors returned while parsing a G-code line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A word is not a letter followed by a number, or a value is missing.
+    InvalidWord,
+    /// A number could not be parsed.
+    InvalidNumber,
+    /// A feed rate is not a positive, finite number.
+    InvalidFeedRate,
+    /// The G or M code is not part of the supported subset.
+    UnsupportedCommand,
+    /// A parameter letter is not valid for the command.
+    UnexpectedParameter(char),
+    /// The line contains parameters but no G or M code.
+    MissingCommand,
+}
+
+/// Errors returned while executing G-code.
+#[derive(Debug)]
+pub enum GCodeError<E> {
+    /// The line could not be parsed.
+    Parse(ParseError),
+    /// A command addresses an axis that is not configured.
+    UnknownAxis(char),
+    /// A move has no feed rate, and none was set by a previous command.
+    MissingFeedRate,
+    /// A feed rate (including `MachineConfig::rapid_feed_rate`) is not a positive, finite number.
+    InvalidFeedRate,
+    /// `MachineConfig::acceleration` is not a positive, finite number.
+    InvalidAcceleration,
+    /// `MachineConfig::steps_per_mm` of the given axis is not a positive, finite number.
+    InvalidStepsPerMm(char),
+    /// An error reported by an axis driver.
+    Axis(E),
+}
+
+impl<E> From<ParseError> for GCodeError<E> {
+    fn from(err: ParseError) -> Self {
+        GCodeError::Parse(err)
+    }
+}
+
+/// Target of a G0/G1 move.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Move {
+    /// Target coordinate of each axis in mm, if given.
+    pub axes: [Option<f32>; MAX_AXES],
+    /// Feed rate in mm/min, if given.
+    pub feed_rate: Option<f32>,
+}
+
+/// A parsed G-code command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// G0: rapid move.
+    RapidMove(Move),
+    /// G1: linear move.
+    LinearMove(Move),
+    /// G28: return the selected axes to position 0. No axis selected means all axes.
+    Home([bool; MAX_AXES]),
+    /// G90: interpret coordinates as absolute positions.
+    AbsolutePositioning,
+    /// G91: interpret coordinates relative to the current position.
+    RelativePositioning,
+    /// M17: enable the selected axes. No axis selected means all axes.
+    EnableMotors([bool; MAX_AXES]),
+    /// M18: disable the selected axes. No axis selected means all axes.
+    DisableMotors([bool; MAX_AXES]),
+    /// M906: set the run current of axes in mA.
+    SetCurrent([Option<u16>; MAX_AXES]),
+}
+
+/// Parses a single line of G-code.
+///
+/// Returns `Ok(None)` for empty lines and lines that only contain comments or a line number.
+pub fn parse_line(line: &str) -> Result<Option<Command>, ParseError> {
+    let mut code: Option<(u8, u16)> = None;
+    let mut axes = [None; MAX_AXES];
+    // Axis letters given without a value, e.g. `G28 X`.
+    let mut flags = [false; MAX_AXES];
+    let mut feed_rate = None;
+    let mut has_params = false;
+
+    for word in Words::new(line) {
+        let (letter, value) = word?;
+        match (letter, value) {
+            (b'G' | b'M', Some(value)) if code.is_none() => {
+                if value < 0.0 || value != (value as u16) as f32 {
+                    return Err(ParseError::UnsupportedCommand);
+                }
+                code = Some((letter, value as u16));
+            }
+            (b'N', Some(_)) => {}
+            (b'F', Some(value)) => {
+                if !(value > 0.0 && value.is_finite()) {
+                    return Err(ParseError::InvalidFeedRate);
+                }
+                feed_rate = Some(value);
+                has_params = true;
+            }
+            _ => match AXIS_LETTERS.iter().position(|&l| l == letter) {
+                Some(i) => {
+                    axes[i] = value;
+                    flags[i] = value.is_none();
+                    has_params = true;
+                }
+                None if value.is_none() => return Err(ParseError::InvalidWord),
+                None => return Err(ParseError::UnexpectedParameter(letter as char)),
+            },
+        }
+    }
+
+    let Some(code) = code else {
+        return if has_params {
+            Err(ParseError::MissingCommand)
+        } else {
+            Ok(None)
+        };
+    };
+    let selected = || {
+        let mut mask = [false; MAX_AXES];
+        for (i, sel) in mask.iter_mut().enumerate() {
+            *sel = axes[i].is_some() || flags[i];
+        }
+        mask
+    };
+    let no_feed = |cmd: Command| match feed_rate {
+        Some(_) => Err(ParseError::UnexpectedParameter('F')),
+        None => Ok(cmd),
+    };
+    let with_values = |cmd: Command| match flags.iter().any(|&f| f) {
+        true => Err(ParseError::InvalidWord),
+        false => Ok(cmd),
+    };
+    let command = match code {
+        (b'G', 0) => with_values(Command::RapidMove(Move { axes, feed_rate }))?,
+        (b'G', 1) => with_values(Command::LinearMove(Move { axes, feed_rate }))?,
+        (b'G', 28) => no_feed(Command::Home(selected()))?,
+        (b'G', 90) => no_feed(Command::AbsolutePositioning)?,
+        (b'G', 91) => no_feed(Command::RelativePositioning)?,
+        (b'M', 17) => no_feed(Command::EnableMotors(selected()))?,
+        (b'M', 18) | (b'M', 84) => no_feed(Command::DisableMotors(selected()))?,
+        (b'M', 906) => {
+            let mut currents = [None; MAX_AXES];
+            for (current, value) in currents.iter_mut().zip(axes) {
+                if let Some(value) = value {
+                    if !(0.0..=u16::MAX as f32).contains(&value) {
+                        return Err(ParseError::InvalidNumber);
+                    }
+                    *current = Some(value as u16);
+                }
+            }
+            with_values(no_feed(Command::SetCurrent(currents))?)?
+        }
+        _ => return Err(ParseError::UnsupportedCommand),
+    };
+    Ok(Some(command))
+}
+
+/// Iterator over the words (letter + optional number) of a G-code line, skipping comments.
+struct Words<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Words<'a> {
+    fn new(line: &'a str) -> Self {
+        Self {
+            bytes: line.as_bytes(),
+            pos: 0,
+        }
+    }
+}
+
+impl Iterator for Words<'_> {
+    type Item = Result<(u8, Option<f32>), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Skip whitespace and comments.
+        loop {
+            match self.bytes.get(self.pos)? {
+                b' ' | b'\t' | b'\r' | b'\n' => self.pos += 1,
+                b';' | b'*' => return None,
+                b'(' => {
+                    while *self.bytes.get(self.pos)? != b')' {
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+        let letter = self.bytes[self.pos].to_ascii_uppercase();
+        self.pos += 1;
+        if !letter.is_ascii_alphabetic() {
+            return Some(Err(ParseError::InvalidWord));
+        }
+        let start = self.pos;
+        while let Some(&b) = self.bytes.get(self.pos) {
+            if b.is_ascii_digit() || b == b'.' || b == b'-' || b == b'+' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        // Only ASCII bytes were consumed, so the slice is valid UTF-8.
+        let number = core::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        if number.is_empty() {
+            return Some(Ok((letter, None)));
+        }
+        Some(
+            number
+                .parse::<f32>()
+                .map(|value| (letter, Some(value)))
+                .map_err(|_| ParseError::InvalidNumber),
+        )
+    }
+}
+
+/// An axis that can be controlled through G-code.
+pub trait GCodeAxis: StepDir {
+    /// Energizes the motor.
+    fn enable(&mut self) -> Result<(), Self::Error>;
+
+    /// De-energizes the motor.
+    fn disable(&mut self) -> Result<(), Self::Error>;
+
+    /// Sets the motor run current in mA.
+    fn set_current_ma(&mut self, milliamps: u16) -> Result<(), Self::Error>;
+}
+
+/// Motion parameters of a `GCodeMachine`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MachineConfig<const N: usize> {
+    /// Steps (at the configured microstep resolution) per mm of each axis.
+    pub steps_per_mm: [f32; N],
+    /// Feed rate used by G0 and G28 in mm/min.
+    pub rapid_feed_rate: f32,
+    /// Path acceleration in mm/s².
+    pub acceleration: f32,
+}
+
+/// Executes G-code commands on N axes (X, Y, Z in this order, N ≤ 3).
+pub struct GCodeMachine<'a, E, const N: usize> {
+    axes: [&'a mut dyn GCodeAxis<Error = E>; N],
+    config: MachineConfig<N>,
+    /// Whether G91 relative positioning is active.
+    relative: bool,
+    /// Feed rate of G1 moves in mm/min, set by the last F word.
+    feed_rate: Option<f32>,
+}
+
+impl<'a, E, const N: usize> GCodeMachine<'a, E, N> {
+    /// Creates a new machine in absolute positioning mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than three axes are given.
+    pub fn new(axes: [&'a mut dyn GCodeAxis<Error = E>; N], config: MachineConfig<N>) -> Self {
+        assert!(N <= MAX_AXES, "at most three axes are supported");
+        Self {
+            axes,
+            config,
+            relative: false,
+            feed_rate: None,
+        }
+    }
+
+    /// Returns the position of each axis in mm.
+    pub fn position_mm(&self) -> [f32; N] {
+        let mut position = [0.0; N];
+        for (i, axis) in self.axes.iter().enumerate() {
+            position[i] = axis.position() as f32 / self.config.steps_per_mm[i];
+        }
+        position
+    }
+
+    /// Parses and executes a single line of G-code, blocking until any motion is complete.
+    pub fn execute_line<D: DelayNs>(
+        &mut self,
+        line: &str,
+        delay: &mut D,
+    ) -> Result<(), GCodeError<E>> {
+        match parse_line(line)? {
+            Some(command) => self.execute(&command, delay),
+            None => Ok(()),
+        }
+    }
+
+    /// Executes a parsed command, blocking until any motion is complete.
+    pub fn execute<D: DelayNs>(
+        &mut self,
+        command: &Command,
+        delay: &mut D,
+    ) -> Result<(), GCodeError<E>> {
+        match *command {
+            Command::RapidMove(m) => {
+                self.check_axes(m.axes.map(|a| a.is_some()))?;
+                if let Some(feed_rate) = m.feed_rate {
+                    self.feed_rate = Some(feed_rate);
+                }
+                self.move_to(&m.axes, self.config.rapid_feed_rate, delay)
+            }
+            Command::LinearMove(m) => {
+                self.check_axes(m.axes.map(|a| a.is_some()))?;
+                if let Some(feed_rate) = m.feed_rate {
+                    self.feed_rate = Some(feed_rate);
+                }
+                let feed_rate = self.feed_rate.ok_or(GCodeError::MissingFeedRate)?;
+                self.move_to(&m.axes, feed_rate, delay)
+            }
+            Command::Home(selected) => {
+                let selected = self.selection(selected)?;
+                let mut targets = [None; MAX_AXES];
+                for (i, target) in targets.iter_mut().enumerate().take(N) {
+                    if selected[i] {
+                        *target = Some(0.0);
+                    }
+                }
+                let relative = core::mem::replace(&mut self.relative, false);
+                let result = self.move_to(&targets, self.config.rapid_feed_rate, delay);
+                self.relative = relative;
+                result
+            }
+            Command::AbsolutePositioning => {
+                self.relative = false;
+                Ok(())
+            }
+            Command::RelativePositioning => {
+                self.relative = true;
+                Ok(())
+            }
+            Command::EnableMotors(selected) => {
+                let selected = self.selection(selected)?;
+                for (i, axis) in self.axes.iter_mut().enumerate() {
+                    if selected[i] {
+                        axis.enable().map_err(GCodeError::Axis)?;
+                    }
+                }
+                Ok(())
+            }
+            Command::DisableMotors(selected) => {
+                let selected = self.selection(selected)?;
+                for (i, axis) in self.axes.iter_mut().enumerate() {
+                    if selected[i] {
+                        axis.disable().map_err(GCodeError::Axis)?;
+                    }
+                }
+                Ok(())
+            }
+            Command::SetCurrent(currents) => {
+                self.check_axes(currents.map(|c| c.is_some()))?;
+                for (axis, current) in self.axes.iter_mut().zip(currents) {
+                    if let Some(milliamps) = current {
+                        axis.set_current_ma(milliamps).map_err(GCodeError::Axis)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns an error if a command addresses an axis beyond the configured ones.
+    fn check_axes(&self, used: [bool; MAX_AXES]) -> Result<(), GCodeError<E>> {
+        match (N..MAX_AXES).find(|&i| used[i]) {
+            Some(i) => Err(GCodeError::UnknownAxis(AXIS_LETTERS[i] as char)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks an axis selection and expands an empty one to all configured axes.
+    fn selection(&self, selected: [bool; MAX_AXES]) -> Result<[bool; MAX_AXES], GCodeError<E>> {
+        self.check_axes(selected)?;
+        if selected.iter().any(|&s| s) {
+            Ok(selected)
+        } else {
+            Ok(core::array::from_fn(|i| i < N))
+        }
+    }
+
+    /// Performs a coordinated move to the given coordinates (in mm) at a path feed rate.
+    fn move_to<D: DelayNs>(
+        &mut self,
+        coordinates: &[Option<f32>; MAX_AXES],
+        feed_rate: f32,
+        delay: &mut D,
+    ) -> Result<(), GCodeError<E>> {
+        if !is_positive(feed_rate) {
+            return Err(GCodeError::InvalidFeedRate);
+        }
+        if !is_positive(self.config.acceleration) {
+            return Err(GCodeError::InvalidAcceleration);
+        }
+        if let Some(i) = (0..N).find(|&i| !is_positive(self.config.steps_per_mm[i])) {
+            return Err(GCodeError::InvalidStepsPerMm(AXIS_LETTERS[i] as char));
+        }
+        let mut targets = [0i64; N];
+        let mut length_sq = 0.0f32;
+        let mut major = 0u64;
+        for (i, axis) in self.axes.iter().enumerate() {
+            let position = axis.position();
+            let steps_per_mm = self.config.steps_per_mm[i];
+            targets[i] = match coordinates[i] {
+                Some(value) if self.relative => {
+                    position + libm::roundf(value * steps_per_mm) as i64
+                }
+                Some(value) => libm::roundf(value * steps_per_mm) as i64,
+                None => position,
+            };
+            let steps = targets[i] - position;
+            let mm = steps as f32 / steps_per_mm;
+            length_sq += mm * mm;
+            major = major.max(steps.unsigned_abs());
+        }
+        if major == 0 {
+            return Ok(());
+        }
+        // Convert path feed rate and acceleration into limits for the major axis.
+        let scale = major as f32 / libm::sqrtf(length_sq);
+        let max_velocity = feed_rate / 60.0 * scale;
+        let acceleration = self.config.acceleration * scale;
+        let axes = self
+            .axes
+            .each_mut()
+            .map(|axis| &mut **axis as &mut dyn StepDir<Error = E>);
+        CoordinatedMove::new(axes, targets, max_velocity, acceleration)
+            .run(delay)
+            .map_err(GCodeError::Axis)
+    }
+}
+
+/// Returns `true` if `value` is a positive, finite number (and not NaN).
+fn is_positive(value: f32) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::Delay;
+    use crate::types::Direction;
+    use core::convert::Infallible;
+
+    /// Axis counting steps and tracking whether it is enabled.
+    #[derive(Default)]
+    struct Axis {
+        position: i64,
+        direction: i64,
+        enabled: bool,
+    }
+
+    impl StepDir for Axis {
+        type Error = Infallible;
+
+        fn set_direction(&mut self, direction: Direction) -> Result<(), Infallible> {
+            self.direction = match direction {
+                Direction::CW => 1,
+                Direction::CCW => -1,
+            };
+            Ok(())
+        }
+
+        fn step(&mut self) -> Result<(), Infallible> {
+            self.position += self.direction;
+            Ok(())
+        }
+
+        fn position(&self) -> i64 {
+            self.position
+        }
+    }
+
+    impl GCodeAxis for Axis {
+        fn enable(&mut self) -> Result<(), Infallible> {
+            self.enabled = true;
+            Ok(())
+        }
+
+        fn disable(&mut self) -> Result<(), Infallible> {
+            self.enabled = false;
+            Ok(())
+        }
+
+        fn set_current_ma(&mut self, _milliamps: u16) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    const CONFIG: MachineConfig<2> = MachineConfig {
+        steps_per_mm: [10.0, 10.0],
+        rapid_feed_rate: 6_000.0,
+        acceleration: 1_000.0,
+    };
+
+    #[test]
+    fn rejects_invalid_feed_rates() {
+        for line in [
+            "G1 X10 F0",
+            "G1 X10 F-600",
+            "G0 F0",
+            "G1 X1 F1000000000000000000000000000000000000000",
+        ] {
+            assert_eq!(parse_line(line), Err(ParseError::InvalidFeedRate), "{line}");
+        }
+        let Ok(Some(Command::LinearMove(m))) = parse_line("G1 X10 F600") else {
+            panic!("G1 not parsed");
+        };
+        assert_eq!(m.feed_rate, Some(600.0));
+    }
+
+    #[test]
+    fn axis_selection() {
+        assert_eq!(
+            parse_line("G28"),
+            Ok(Some(Command::Home([false, false, false])))
+        );
+        assert_eq!(
+            parse_line("G28 X Z"),
+            Ok(Some(Command::Home([true, false, true])))
+        );
+        assert_eq!(
+            parse_line("M18 Y"),
+            Ok(Some(Command::DisableMotors([false, true, false])))
+        );
+    }
+
+    #[test]
+    fn selects_configured_axes() {
+        let (mut x, mut y) = (Axis::default(), Axis::default());
+        let mut delay = Delay(Default::default());
+        let mut machine = GCodeMachine::new([&mut x, &mut y], CONFIG);
+        machine.execute_line("M17", &mut delay).unwrap();
+        machine.execute_line("G0 X2 Y-1", &mut delay).unwrap();
+        assert_eq!(machine.position_mm(), [2.0, -1.0]);
+        machine.execute_line("G28 Y", &mut delay).unwrap();
+        assert_eq!(machine.position_mm(), [2.0, 0.0]);
+        for line in ["G28 Z", "M17 Z", "M18 X Z"] {
+            assert!(
+                matches!(
+                    machine.execute_line(line, &mut delay),
+                    Err(GCodeError::UnknownAxis('Z'))
+                ),
+                "{line}"
+            );
+        }
+        machine.execute_line("G28", &mut delay).unwrap();
+        assert_eq!(machine.position_mm(), [0.0, 0.0]);
+        machine.execute_line("M18 Y", &mut delay).unwrap();
+        assert!(x.enabled && !y.enabled);
+    }
+
+    #[test]
+    fn rejects_invalid_acceleration() {
+        for acceleration in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let (mut x, mut y) = (Axis::default(), Axis::default());
+            let config = MachineConfig {
+                acceleration,
+                ..CONFIG
+            };
+            let mut machine = GCodeMachine::new([&mut x, &mut y], config);
+            assert!(
+                matches!(
+                    machine.execute_line("G0 X1", &mut Delay(Default::default())),
+                    Err(GCodeError::InvalidAcceleration)
+                ),
+                "{acceleration}"
+            );
+            assert_eq!(machine.position_mm(), [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_steps_per_mm() {
+        for steps_per_mm in [0.0, -10.0, f32::NAN, f32::INFINITY] {
+            let (mut x, mut y) = (Axis::default(), Axis::default());
+            let config = MachineConfig {
+                steps_per_mm: [10.0, steps_per_mm],
+                ..CONFIG
+            };
+            let mut machine = GCodeMachine::new([&mut x, &mut y], config);
+            // Also rejected for moves that leave the misconfigured axis alone.
+            assert!(
+                matches!(
+                    machine.execute_line("G1 X1 F600", &mut Delay(Default::default())),
+                    Err(GCodeError::InvalidStepsPerMm('Y'))
+                ),
+                "{steps_per_mm}"
+            );
+            assert_eq!(x.position, 0);
+        }
+    }
+}
@@ -8,12 +8,22 @@
 //! - SPI communication with 40-bit transfers (8-bit address + 32-bit data)
 //! - Bitfield manipulation using the `bitfield` crate for register definitions
 //! - A high-level API for motor control (current settings, microstepping, stepping, etc.)
+//! - Software step generation with trapezoidal (see `motion`) and jerk-limited (see `scurve`)
+//!   acceleration profiles, executed blocking or from a timer interrupt (see `stepper`)
+//! - Coordinated straight-line moves across several drivers (see `coordinated`)
+//! - Limit switch inputs that stop motion and home axes (see `limits`)
+//! - Custom microstep waveforms (see `waveform`)
+//! - Direct coil current control (see `direct`)
+//! - Hardware and software emergency stop (see `estop`)
+//! - Fault monitoring through the DIAG0/DIAG1 outputs (see `fault`)
+//! - Step loss monitoring in DcStep operation (see `monitor`)
+//! - Overtemperature management with current derating (see `thermal`)
+//! - A wiring self-test for commissioning (see `wiring`)
+//! - A small G-code interpreter for bench fixtures (see `gcode`)
 //!
 //! ## Example Usage
 //!
 //! ```no_run
-//! use embedded_hal::spi::SpiDevice;
-//! use embedded_hal::digital::v2::OutputPin;
 //! use tmc2160_driver::Tmc2160;
 //!
 //! // Your hardware-specific SPI and GPIO types would be used here.
@@ -26,10 +36,40 @@
 //!
 //! For detailed documentation, see the module docs.
 
+pub mod coordinated;
+pub mod direct;
+pub mod estop;
+pub mod fault;
+pub mod gcode;
+pub mod limits;
+#[cfg(test)]
+mod mock;
+pub mod monitor;
+pub mod motion;
 pub mod registers;
+pub mod scurve;
+pub mod stepper;
+pub mod thermal;
 pub mod tmc2160;
 pub mod types;
+pub mod waveform;
+pub mod wiring;
 
 // Re-export key public types for ease of use.
+pub use coordinated::{CoordinatedMove, CoordinatedStep};
+pub use direct::DirectModeController;
+pub use estop::StopInput;
+pub use fault::{FaultAction, FaultEvent, FaultMonitor};
+pub use limits::{ActiveLevel, Homing, Limit, LimitSwitch, LimitSwitches};
+pub use monitor::{LinkMonitor, StepLoss, StepLossMonitor};
+pub use motion::{Motion, MotionEvent, StepDir, StepGuard, StepInterval, StepProfile};
+pub use scurve::SCurve;
+pub use stepper::StepperTask;
+pub use thermal::{ThermalConfig, ThermalEvent, ThermalPolicy};
 pub use tmc2160::Tmc2160;
-pub use types::{Direction, DriverStatus, Error, MicrostepResolution};
+pub use types::{
+    Backlash, DcStepConfig, Direction, DriverStatus, Error, ErrorKind, InvalidArgument, LinkFault,
+    MicrostepResolution, OtSelect, StepTiming, SteppingMode,
+};
+pub use waveform::{FitError, MicrostepTable, Waveform, WaveformError};
+pub use wiring::{WiringReport, WiringTest};
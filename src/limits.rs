@@ -0,0 +1,166 @@
+//! Limit switch and end-stop inputs.
+//!
+//! `LimitSwitches` holds optional min/max end-stop inputs with configurable polarity. It acts as a
+//! `StepGuard` for `StepperTask` and `Tmc2160::run_guarded`: before each step, the switch in the
+//! direction of travel is checked, and motion stops with `MotionEvent::LimitReached` if it has
+//! tripped. Steps away from a tripped switch remain possible, so an axis can always be backed off.
+//!
+//! The min switch guards steps in `Direction::CCW` (decreasing position), the max switch guards
+//! steps in `Direction::CW`. `Tmc2160::home_to_switch` uses the switches for homing, as an
+//! alternative to sensorless (StallGuard) homing.
+
+use crate::motion::{MotionEvent, StepGuard};
+use crate::types::Direction;
+use embedded_hal::digital::InputPin;
+
+/// Input level at which a switch is considered triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveLevel {
+    /// The switch is triggered when the input is high (e.g. normally open switch to VCC).
+    High,
+    /// The switch is triggered when the input is low (e.g. normally open switch to GND).
+    Low,
+}
+
+/// End of travel guarded by a limit switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Lower end of travel, reached in `Direction::CCW`.
+    Min,
+    /// Upper end of travel, reached in `Direction::CW`.
+    Max,
+}
+
+impl Limit {
+    /// Returns the direction of travel towards this limit.
+    pub fn direction(self) -> Direction {
+        match self {
+            Limit::Min => Direction::CCW,
+            Limit::Max => Direction::CW,
+        }
+    }
+}
+
+/// A single limit switch input.
+#[derive(Debug)]
+pub struct LimitSwitch<P> {
+    pin: P,
+    active: ActiveLevel,
+}
+
+impl<P: InputPin> LimitSwitch<P> {
+    /// Creates a limit switch on `pin`, triggered at the given input level.
+    pub fn new(pin: P, active: ActiveLevel) -> Self {
+        Self { pin, active }
+    }
+
+    /// Returns `true` if the switch is currently triggered.
+    pub fn is_triggered(&mut self) -> Result<bool, P::Error> {
+        match self.active {
+            ActiveLevel::High => self.pin.is_high(),
+            ActiveLevel::Low => self.pin.is_low(),
+        }
+    }
+
+    /// Releases the input pin.
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+/// Optional min and max limit switches of one axis.
+#[derive(Debug)]
+pub struct LimitSwitches<P> {
+    min: Option<LimitSwitch<P>>,
+    max: Option<LimitSwitch<P>>,
+}
+
+impl<P: InputPin> LimitSwitches<P> {
+    /// Creates a set of limit switches. Either switch may be omitted.
+    pub fn new(min: Option<LimitSwitch<P>>, max: Option<LimitSwitch<P>>) -> Self {
+        Self { min, max }
+    }
+
+    /// Returns `true` if the switch for `limit` is fitted and currently triggered.
+    pub fn is_triggered(&mut self, limit: Limit) -> Result<bool, P::Error> {
+        let switch = match limit {
+            Limit::Min => self.min.as_mut(),
+            Limit::Max => self.max.as_mut(),
+        };
+        match switch {
+            Some(switch) => switch.is_triggered(),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns `true` if a switch is fitted for `limit`.
+    pub fn has(&self, limit: Limit) -> bool {
+        match limit {
+            Limit::Min => self.min.is_some(),
+            Limit::Max => self.max.is_some(),
+        }
+    }
+
+    /// Releases the switches.
+    pub fn release(self) -> (Option<LimitSwitch<P>>, Option<LimitSwitch<P>>) {
+        (self.min, self.max)
+    }
+}
+
+impl<P: InputPin> StepGuard<P::Error> for LimitSwitches<P> {
+    fn check(&mut self, direction: Direction) -> Result<Option<MotionEvent>, P::Error> {
+        let limit = match direction {
+            Direction::CW => Limit::Max,
+            Direction::CCW => Limit::Min,
+        };
+        if self.is_triggered(limit)? {
+            Ok(Some(MotionEvent::LimitReached(limit)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Parameters of a switch-based homing run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homing {
+    /// Switch to home against.
+    pub limit: Limit,
+    /// Velocity while searching for the switch, in steps per second.
+    pub velocity: f32,
+    /// Velocity while backing off the switch, in steps per second.
+    pub backoff_velocity: f32,
+    /// Maximum number of steps in each phase before homing fails.
+    pub max_steps: u32,
+    /// Position assigned to the point where the switch releases, in steps.
+    pub position: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::Input;
+
+    #[test]
+    fn active_level() {
+        let mut high = LimitSwitch::new(Input::new(&[true, false]), ActiveLevel::High);
+        assert_eq!(high.is_triggered(), Ok(true));
+        assert_eq!(high.is_triggered(), Ok(false));
+        let mut low = LimitSwitch::new(Input::new(&[true, false]), ActiveLevel::Low);
+        assert_eq!(low.is_triggered(), Ok(false));
+        assert_eq!(low.is_triggered(), Ok(true));
+    }
+
+    #[test]
+    fn guards_direction_of_travel() {
+        let min = LimitSwitch::new(Input::new(&[true]), ActiveLevel::High);
+        let mut switches = LimitSwitches::new(Some(min), None);
+        assert_eq!(
+            switches.check(Direction::CCW),
+            Ok(Some(MotionEvent::LimitReached(Limit::Min)))
+        );
+        // Moving away from the tripped switch is allowed, and a missing switch never trips.
+        assert_eq!(switches.check(Direction::CW), Ok(None));
+        assert!(switches.has(Limit::Min) && !switches.has(Limit::Max));
+    }
+}
@@ -0,0 +1,381 @@
+//! Host-side mocks for unit tests: a simulated clock, recording pins and an SPI register file.
+
+extern crate std;
+
+use crate::motion::{StepInterval, StepProfile};
+use crate::stepper::StepperTask;
+use crate::tmc2160::Tmc2160;
+use crate::types::Direction;
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, ErrorType, InputPin, OutputPin};
+use embedded_hal::spi::{self, SpiBus};
+use std::rc::Rc;
+use std::vec::Vec;
+
+/// Simulated monotonic clock in nanoseconds.
+#[derive(Debug, Clone, Default)]
+pub struct Clock(Rc<Cell<u64>>);
+
+impl Clock {
+    pub fn now(&self) -> u64 {
+        self.0.get()
+    }
+
+    pub fn set(&self, now: u64) {
+        assert!(now >= self.now(), "clock must not run backwards");
+        self.0.set(now);
+    }
+
+    pub fn advance(&self, ns: u64) {
+        self.0.set(self.0.get() + ns);
+    }
+}
+
+/// Error of a mock pin set up to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinFault;
+
+impl digital::Error for PinFault {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+/// Output pin recording every level change with the time of the clock.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    clock: Clock,
+    edges: Rc<RefCell<Vec<(u64, bool)>>>,
+    /// Whether setting the pin fails with `PinFault`, shared between clones.
+    pub fail: Rc<Cell<bool>>,
+}
+
+impl Pin {
+    pub fn new(clock: &Clock) -> Self {
+        Self {
+            clock: clock.clone(),
+            edges: Rc::default(),
+            fail: Rc::default(),
+        }
+    }
+
+    /// Returns the recorded level changes as (time, level).
+    pub fn edges(&self) -> Vec<(u64, bool)> {
+        self.edges.borrow().clone()
+    }
+
+    fn set(&mut self, level: bool) -> Result<(), PinFault> {
+        if self.fail.get() {
+            return Err(PinFault);
+        }
+        let mut edges = self.edges.borrow_mut();
+        if edges.last().map(|&(_, last)| last) != Some(level) {
+            edges.push((self.clock.now(), level));
+        }
+        Ok(())
+    }
+}
+
+impl ErrorType for Pin {
+    type Error = PinFault;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), PinFault> {
+        self.set(false)
+    }
+
+    fn set_high(&mut self) -> Result<(), PinFault> {
+        self.set(true)
+    }
+}
+
+/// Input pin returning the given levels on successive reads, then repeating the last one.
+#[derive(Debug, Clone)]
+pub struct Input(Rc<RefCell<Vec<bool>>>);
+
+impl Input {
+    pub fn new(levels: &[bool]) -> Self {
+        Self(Rc::new(RefCell::new(levels.to_vec())))
+    }
+
+    fn read(&self) -> bool {
+        let mut levels = self.0.borrow_mut();
+        if levels.len() > 1 {
+            levels.remove(0)
+        } else {
+            levels[0]
+        }
+    }
+}
+
+impl ErrorType for Input {
+    type Error = PinFault;
+}
+
+impl InputPin for Input {
+    fn is_high(&mut self) -> Result<bool, PinFault> {
+        Ok(self.read())
+    }
+
+    fn is_low(&mut self) -> Result<bool, PinFault> {
+        Ok(!self.read())
+    }
+}
+
+/// Delay advancing the simulated clock.
+#[derive(Debug, Clone)]
+pub struct Delay(pub Clock);
+
+impl DelayNs for Delay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.advance(ns as u64);
+    }
+}
+
+/// Address of GSTAT, whose flags are cleared by writing 1.
+const GSTAT: usize = 0x01;
+
+/// SPI bus answering like a TMC2160 with a plain register file (no write-only registers), except
+/// for the write-1-to-clear GSTAT.
+#[derive(Debug, Clone)]
+pub struct Spi {
+    /// Register contents, shared between clones.
+    pub regs: Rc<RefCell<[u32; 128]>>,
+    /// Data bits that read as 1 regardless of the register contents, shared between clones.
+    pub stuck: Rc<Cell<u32>>,
+    /// Register writes as (address, value) in order, shared between clones.
+    pub writes: Rc<RefCell<Vec<(u8, u32)>>>,
+    pending: u32,
+}
+
+impl Default for Spi {
+    fn default() -> Self {
+        Self {
+            regs: Rc::new(RefCell::new([0; 128])),
+            stuck: Rc::default(),
+            writes: Rc::default(),
+            pending: 0,
+        }
+    }
+}
+
+impl Spi {
+    pub fn reg(&self, addr: u8) -> u32 {
+        self.regs.borrow()[addr as usize]
+    }
+
+    pub fn set_reg(&self, addr: u8, value: u32) {
+        self.regs.borrow_mut()[addr as usize] = value;
+    }
+
+    /// Returns the values written to the register at `addr`, in order.
+    pub fn writes_to(&self, addr: u8) -> Vec<u32> {
+        self.writes
+            .borrow()
+            .iter()
+            .filter(|&&(to, _)| to == addr)
+            .map(|&(_, value)| value)
+            .collect()
+    }
+}
+
+impl spi::ErrorType for Spi {
+    type Error = Infallible;
+}
+
+impl SpiBus for Spi {
+    fn read(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+        read[0] = 0;
+        read[1..5].copy_from_slice(&(self.pending | self.stuck.get()).to_be_bytes());
+        let addr = (write[0] & 0x7F) as usize;
+        let mut regs = self.regs.borrow_mut();
+        if write[0] & 0x80 != 0 {
+            let value = u32::from_be_bytes([write[1], write[2], write[3], write[4]]);
+            self.writes.borrow_mut().push((addr as u8, value));
+            if addr == GSTAT {
+                // GSTAT flags are cleared by writing 1.
+                regs[addr] &= !value;
+            } else {
+                regs[addr] = value;
+            }
+        }
+        self.pending = regs[addr];
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Profile issuing `count` steps in `direction`, `delay_ns` apart.
+#[derive(Debug, Clone, Copy)]
+pub struct Steps {
+    pub count: u32,
+    pub delay_ns: u32,
+    pub direction: Direction,
+}
+
+impl Steps {
+    pub fn new(count: u32, delay_ns: u32, direction: Direction) -> Self {
+        Self {
+            count,
+            delay_ns,
+            direction,
+        }
+    }
+}
+
+impl StepProfile for Steps {
+    fn next_step_interval(&mut self) -> Option<StepInterval> {
+        self.count = self.count.checked_sub(1)?;
+        Some(StepInterval {
+            delay_ns: self.delay_ns,
+            direction: self.direction,
+        })
+    }
+}
+
+/// Profile issuing the given steps in order.
+#[derive(Debug, Clone)]
+pub struct Sequence(pub Vec<StepInterval>);
+
+impl StepProfile for Sequence {
+    fn next_step_interval(&mut self) -> Option<StepInterval> {
+        (!self.0.is_empty()).then(|| self.0.remove(0))
+    }
+}
+
+pub type MockDriver = Tmc2160<Spi, Pin, Pin, Pin, Pin, Delay>;
+
+/// A driver on mocks, with the clock, the SPI register file and the EN, DIR and STEP pins for
+/// inspection.
+pub struct Bench {
+    pub driver: MockDriver,
+    pub clock: Clock,
+    pub spi: Spi,
+    pub en: Pin,
+    pub dir: Pin,
+    pub step: Pin,
+}
+
+impl Bench {
+    pub fn new() -> Self {
+        Self::with_clock(&Clock::default())
+    }
+
+    /// Creates a driver on the given clock, e.g. to run several axes in parallel.
+    pub fn with_clock(clock: &Clock) -> Self {
+        let clock = clock.clone();
+        let spi = Spi::default();
+        // IOIN.VERSION of the TMC2160.
+        spi.set_reg(0x04, 0x3000_0000);
+        let en = Pin::new(&clock);
+        let dir = Pin::new(&clock);
+        let step = Pin::new(&clock);
+        let driver = Tmc2160::new(
+            spi.clone(),
+            Pin::new(&clock),
+            en.clone(),
+            dir.clone(),
+            step.clone(),
+            Delay(clock.clone()),
+        )
+        .unwrap();
+        Self {
+            driver,
+            clock,
+            spi,
+            en,
+            dir,
+            step,
+        }
+    }
+
+    /// Runs `task` to completion, calling `tick()` exactly at the returned deadlines. Returns the
+    /// deadlines.
+    pub fn run_task<P: StepProfile>(&mut self, task: &mut StepperTask<P>) -> Vec<u64> {
+        let mut deadlines = Vec::new();
+        while let Some(deadline) = task.tick(&mut self.driver, self.clock.now()).unwrap() {
+            deadlines.push(deadline);
+            self.clock.set(deadline);
+        }
+        deadlines
+    }
+
+    /// Asserts that DIR never changed within `hold` nanoseconds after a step edge, and that no
+    /// step edge followed a DIR change within `setup` nanoseconds.
+    pub fn assert_dir_timing(&self, double_edge: bool, setup: u64, hold: u64) {
+        let steps = self.step_edges(double_edge);
+        for change in self.dir_changes() {
+            if let Some(last) = steps.iter().rev().find(|&&step| step <= change) {
+                assert!(
+                    change - last >= hold,
+                    "DIR changed {} ns after a step at {last}",
+                    change - last
+                );
+            }
+            if let Some(next) = steps.iter().find(|&&step| step >= change) {
+                assert!(
+                    next - change >= setup,
+                    "step {} ns after a DIR change at {change}",
+                    next - change
+                );
+            }
+        }
+    }
+
+    /// Times of the STEP edges that issue a step: rising edges, or every edge after the initial
+    /// level in double-edge mode.
+    pub fn step_edges(&self, double_edge: bool) -> Vec<u64> {
+        self.step
+            .edges()
+            .into_iter()
+            .skip(1)
+            .filter(|&(_, level)| double_edge || level)
+            .map(|(time, _)| time)
+            .collect()
+    }
+
+    /// Returns the (high, low) times of the STEP pulses, excluding the level before the first and
+    /// after the last edge.
+    pub fn pulse_widths(&self) -> (Vec<u64>, Vec<u64>) {
+        let edges = self.step.edges();
+        let mut high = Vec::new();
+        let mut low = Vec::new();
+        for pair in edges.windows(2).skip(1) {
+            let width = pair[1].0 - pair[0].0;
+            if pair[0].1 {
+                high.push(width);
+            } else {
+                low.push(width);
+            }
+        }
+        (high, low)
+    }
+
+    /// Times at which DIR changed after its initial level.
+    pub fn dir_changes(&self) -> Vec<u64> {
+        self.dir
+            .edges()
+            .into_iter()
+            .skip(1)
+            .map(|(time, _)| time)
+            .collect()
+    }
+}
@@ -0,0 +1,258 @@
+//! Runtime monitoring of the driver.
+//!
+//! `StepLossMonitor` watches the LOST_STEPS counter. In DcStep operation the motor slows down under
+//! load; STEP pulses that arrive while it cannot follow are skipped and counted in LOST_STEPS. The
+//! monitor polls the counter, reports every change as a `StepLoss` event through a callback and
+//! can reconcile the tracked position with it. Once more steps than the configured tolerance have
+//! been lost, the motion engine is frozen like after `Tmc2160::emergency_stop`.
+//!
+//! `LinkMonitor` periodically runs `Tmc2160::check_link` on long-running machines, so a loose or
+//! damaged SPI connection is detected rather than read as valid data.
+//!
+//! ```ignore
+//! let mut monitor = StepLossMonitor::new(16, |loss: StepLoss| log_loss(loss.count, loss.direction));
+//! monitor.set_correction(true);
+//! // Periodically, e.g. from the main loop:
+//! monitor.poll(&mut driver)?;
+//! ```
+
+use crate::registers::{LostSteps, Register};
+use crate::tmc2160::Tmc2160;
+use crate::types::{Direction, Error};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Width of the LOST_STEPS counter in bits.
+const LOST_STEPS_BITS: u32 = 20;
+
+/// Steps skipped by the driver since the previous poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepLoss {
+    /// Number of skipped input steps (of the current microstep resolution).
+    pub count: u32,
+    /// Direction of the skipped steps.
+    pub direction: Direction,
+}
+
+/// Polls LOST_STEPS and reconciles it with the tracked position.
+#[derive(Debug)]
+pub struct StepLossMonitor<F> {
+    /// Callback invoked for every change of the counter.
+    on_loss: F,
+    /// Number of lost steps tolerated before motion is stopped.
+    tolerance: u32,
+    /// Whether the tracked position is corrected for lost steps.
+    correct: bool,
+    /// LOST_STEPS value at the previous poll.
+    last: Option<u32>,
+    /// Number of lost steps since the monitor was reset.
+    total: u32,
+}
+
+impl<F: FnMut(StepLoss)> StepLossMonitor<F> {
+    /// Creates a monitor that stops motion once more than `tolerance` steps were lost.
+    ///
+    /// `on_loss` is called whenever the counter changes. Position correction is disabled.
+    pub fn new(tolerance: u32, on_loss: F) -> Self {
+        Self {
+            on_loss,
+            tolerance,
+            correct: false,
+            last: None,
+            total: 0,
+        }
+    }
+
+    /// Enables or disables correction of the tracked position for lost steps.
+    ///
+    /// With correction, the position follows the motor; without it, `Tmc2160::position` reports
+    /// where the motor was commanded to.
+    pub fn set_correction(&mut self, correct: bool) {
+        self.correct = correct;
+    }
+
+    /// Returns the number of lost steps since the monitor was reset.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Resets the lost step count, e.g. after homing.
+    ///
+    /// The next poll takes a new reference value of LOST_STEPS.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.total = 0;
+    }
+
+    /// Reads LOST_STEPS and handles any change since the previous poll.
+    ///
+    /// The first poll after creation or `reset()` only takes a reference value. Returns the steps
+    /// lost since the previous poll, if any. If the total exceeds the tolerance, the motion
+    /// engine is frozen with `Tmc2160::emergency_stop` and `Error::StepLoss` is returned.
+    pub fn poll<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+    ) -> Result<Option<StepLoss>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+    {
+        let counter = LostSteps(driver.read_register(Register::LostSteps)?).lost_steps();
+        let Some(last) = self.last.replace(counter) else {
+            return Ok(None);
+        };
+        // Sign-extend the wrapping difference of the 20-bit counter.
+        let shift = 32 - LOST_STEPS_BITS;
+        let count = ((counter.wrapping_sub(last) << shift) as i32) >> shift;
+        if count == 0 {
+            return Ok(None);
+        }
+        if self.correct {
+            driver.discard_steps(count);
+        }
+        let loss = StepLoss {
+            count: count.unsigned_abs(),
+            direction: if count > 0 {
+                Direction::CW
+            } else {
+                Direction::CCW
+            },
+        };
+        (self.on_loss)(loss);
+        self.total = self.total.saturating_add(loss.count);
+        if self.total > self.tolerance {
+            driver.emergency_stop()?;
+            return Err(Error::StepLoss { count: self.total });
+        }
+        Ok(Some(loss))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::Bench;
+    use std::vec::Vec;
+
+    fn set_lost_steps(bench: &Bench, value: u32) {
+        bench.spi.set_reg(Register::LostSteps as u8, value);
+    }
+
+    #[test]
+    fn sign_extends_wrapping_counter() {
+        let mut bench = Bench::new();
+        let mut losses = Vec::new();
+        let mut monitor = StepLossMonitor::new(100, |loss| losses.push(loss));
+        set_lost_steps(&bench, 0xF_FFFE);
+        assert_eq!(monitor.poll(&mut bench.driver).unwrap(), None);
+        set_lost_steps(&bench, 0x0_0001);
+        monitor.poll(&mut bench.driver).unwrap();
+        set_lost_steps(&bench, 0xF_FFFF);
+        monitor.poll(&mut bench.driver).unwrap();
+        assert_eq!(monitor.poll(&mut bench.driver).unwrap(), None);
+        assert_eq!(monitor.total(), 5);
+        assert_eq!(
+            losses,
+            [
+                StepLoss {
+                    count: 3,
+                    direction: Direction::CW,
+                },
+                StepLoss {
+                    count: 2,
+                    direction: Direction::CCW,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn corrects_position() {
+        let mut bench = Bench::new();
+        for _ in 0..10 {
+            bench.driver.step().unwrap();
+        }
+        let mut monitor = StepLossMonitor::new(100, |_| {});
+        monitor.poll(&mut bench.driver).unwrap();
+        set_lost_steps(&bench, 4);
+        monitor.poll(&mut bench.driver).unwrap();
+        assert_eq!(bench.driver.position(), 10);
+
+        monitor.set_correction(true);
+        set_lost_steps(&bench, 7);
+        monitor.poll(&mut bench.driver).unwrap();
+        assert_eq!(bench.driver.position(), 7);
+    }
+
+    #[test]
+    fn stops_when_tolerance_is_exceeded() {
+        let mut bench = Bench::new();
+        let mut monitor = StepLossMonitor::new(5, |_| {});
+        monitor.poll(&mut bench.driver).unwrap();
+        set_lost_steps(&bench, 3);
+        monitor.poll(&mut bench.driver).unwrap();
+        assert!(!bench.driver.is_emergency_stopped());
+        set_lost_steps(&bench, 6);
+        let err = monitor.poll(&mut bench.driver);
+        assert!(matches!(err, Err(Error::StepLoss { count: 6 })));
+        assert!(bench.driver.is_emergency_stopped());
+
+        monitor.reset();
+        assert_eq!(monitor.total(), 0);
+        assert_eq!(monitor.poll(&mut bench.driver).unwrap(), None);
+    }
+}
+
+/// Periodic SPI link check.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkMonitor {
+    /// Interval between checks in nanoseconds.
+    period_ns: u64,
+    /// Time at which the next check is due.
+    next_check: Option<u64>,
+}
+
+impl LinkMonitor {
+    /// Creates a monitor that checks the link every `period_ns` nanoseconds.
+    ///
+    /// The first call to `poll` performs a check.
+    pub fn new(period_ns: u64) -> Self {
+        Self {
+            period_ns,
+            next_check: None,
+        }
+    }
+
+    /// Runs `Tmc2160::check_link` if the period has elapsed since the last check.
+    ///
+    /// `now` is the current time of a monotonic clock in nanoseconds. Returns `Ok(true)` if a check
+    /// was performed and passed, `Ok(false)` if none was due, and `Error::LinkFault` if the check
+    /// failed. The check only reads registers, so it is safe during motion.
+    pub fn poll<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+    ) -> Result<bool, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+    {
+        if self.next_check.is_some_and(|next| now < next) {
+            return Ok(false);
+        }
+        self.next_check = Some(now + self.period_ns);
+        driver.check_link()?;
+        Ok(true)
+    }
+}
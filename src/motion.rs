@@ -0,0 +1,478 @@
+//! Software step generation with trapezoidal acceleration profiles.
+//!
+//! This module computes the time between consecutive STEP pulses for moves with a bounded
+//! velocity and acceleration. Intervals are derived with David Austin's real-time approximation
+//! ("Generate stepper-motor speed profiles in real time", 2005):
+//!
+//! - The first interval is `c0 = 0.676 * sqrt(2 / a)`.
+//! - Each following interval is `cn = cn-1 - 2 * cn-1 / (4n + 1)`, where `n` is the position on the
+//!   ramp (positive while accelerating, negative while decelerating).
+//! - Intervals never drop below `1 / max_velocity`.
+//!
+//! A `Motion` (or any other `StepProfile`, such as an `SCurve`) can either be executed blocking
+//! through `Tmc2160::run`, or polled with `next_step_interval()` from timer-driven code. Positions
+//! are in (micro)steps; positive movement is mapped to `Direction::CW`.
+
+use crate::limits::Limit;
+use crate::types::Direction;
+
+/// Outcome of executing a motion profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionEvent {
+    /// The profile ran to completion.
+    Completed,
+    /// The limit switch in the direction of travel tripped and motion stopped.
+    LimitReached(Limit),
+    /// An emergency stop froze the motion engine; the position is uncertain.
+    EmergencyStop,
+}
+
+/// A check performed before every step, which can stop motion (e.g. `LimitSwitches`).
+pub trait StepGuard<E> {
+    /// Called before each step in `direction`. Returning an event stops motion before the step
+    /// is issued.
+    fn check(&mut self, direction: Direction) -> Result<Option<MotionEvent>, E>;
+}
+
+/// No checks; motion only ends when the profile is complete.
+impl<E> StepGuard<E> for () {
+    fn check(&mut self, _direction: Direction) -> Result<Option<MotionEvent>, E> {
+        Ok(None)
+    }
+}
+
+impl<E, G: StepGuard<E> + ?Sized> StepGuard<E> for &mut G {
+    fn check(&mut self, direction: Direction) -> Result<Option<MotionEvent>, E> {
+        (**self).check(direction)
+    }
+}
+
+/// Timing of the next step produced by a motion profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInterval {
+    /// Time to wait before issuing the step, in nanoseconds.
+    pub delay_ns: u32,
+    /// Direction of the step.
+    pub direction: Direction,
+}
+
+/// A source of step timings, such as a `Motion` or `SCurve` planner.
+pub trait StepProfile {
+    /// Advances the profile by one step and returns its timing, or `None` when the move is
+    /// complete.
+    fn next_step_interval(&mut self) -> Option<StepInterval>;
+}
+
+impl<P: StepProfile + ?Sized> StepProfile for &mut P {
+    fn next_step_interval(&mut self) -> Option<StepInterval> {
+        (**self).next_step_interval()
+    }
+}
+
+/// A STEP/DIR handle of one axis, used by multi-axis motion code such as `CoordinatedMove`.
+pub trait StepDir {
+    /// Error type of the underlying driver.
+    type Error;
+
+    /// Sets the direction of the following steps.
+    fn set_direction(&mut self, direction: Direction) -> Result<(), Self::Error>;
+
+    /// Issues a single step in the current direction.
+    fn step(&mut self) -> Result<(), Self::Error>;
+
+    /// Returns the absolute position of the axis in steps.
+    fn position(&self) -> i64;
+
+    /// Returns the number of backlash compensation steps pending after a direction reversal,
+    /// which `step` would otherwise issue first. Axes without compensation return 0.
+    fn pending_backlash(&self) -> u32 {
+        0
+    }
+
+    /// Returns the minimum time between backlash compensation steps in nanoseconds.
+    fn backlash_interval_ns(&self) -> u32 {
+        0
+    }
+
+    /// Issues one pending backlash compensation step, without counting it in the position and
+    /// without delay. Does nothing if no compensation step is pending.
+    fn backlash_step(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Trapezoidal motion planner for STEP/DIR driven motors.
+#[derive(Debug, Clone)]
+pub struct Motion {
+    /// Maximum velocity in steps per second.
+    max_velocity: f32,
+    /// Acceleration in steps per second squared.
+    acceleration: f32,
+    /// Current position in steps.
+    position: i64,
+    /// Target position in steps.
+    target: i64,
+    /// Current signed velocity in steps per second.
+    speed: f32,
+    /// Position on the ramp (positive while accelerating, negative while decelerating).
+    n: i64,
+    /// Initial step interval in seconds.
+    c0: f32,
+    /// Last step interval in seconds.
+    cn: f32,
+    /// Minimum step interval in seconds (at `max_velocity`).
+    cmin: f32,
+    /// Direction of the current or last step.
+    direction: Direction,
+}
+
+impl Motion {
+    /// Creates a new motion planner at position 0.
+    ///
+    /// - `max_velocity` is given in steps per second and must be greater than 0.
+    /// - `acceleration` is given in steps per second squared and must be greater than 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_velocity` or `acceleration` is not positive.
+    pub fn new(max_velocity: f32, acceleration: f32) -> Self {
+        assert!(max_velocity > 0.0, "max_velocity must be positive");
+        assert!(acceleration > 0.0, "acceleration must be positive");
+        Self {
+            max_velocity,
+            acceleration,
+            position: 0,
+            target: 0,
+            speed: 0.0,
+            n: 0,
+            c0: initial_interval(acceleration),
+            cn: 0.0,
+            cmin: 1.0 / max_velocity,
+            direction: Direction::CW,
+        }
+    }
+
+    /// Sets the maximum velocity in steps per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_velocity` is not positive.
+    pub fn set_max_velocity(&mut self, max_velocity: f32) {
+        assert!(max_velocity > 0.0, "max_velocity must be positive");
+        self.max_velocity = max_velocity;
+        self.cmin = 1.0 / max_velocity;
+        // While accelerating, restart the ramp position from the current speed so a lower limit
+        // is reached through a regular deceleration.
+        if self.n > 0 {
+            self.n = self.steps_to_stop();
+        }
+    }
+
+    /// Sets the acceleration in steps per second squared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `acceleration` is not positive.
+    pub fn set_acceleration(&mut self, acceleration: f32) {
+        assert!(acceleration > 0.0, "acceleration must be positive");
+        if self.n != 0 {
+            // Keep the current speed by rescaling the ramp position.
+            self.n = (self.n as f32 * (self.acceleration / acceleration)) as i64;
+        }
+        self.acceleration = acceleration;
+        self.c0 = initial_interval(acceleration);
+    }
+
+    /// Returns the maximum velocity in steps per second.
+    pub fn max_velocity(&self) -> f32 {
+        self.max_velocity
+    }
+
+    /// Returns the acceleration in steps per second squared.
+    pub fn acceleration(&self) -> f32 {
+        self.acceleration
+    }
+
+    /// Starts a move to an absolute position.
+    ///
+    /// A new target may be set while moving; the planner decelerates and reverses if required.
+    pub fn move_to(&mut self, position: i64) {
+        self.target = position;
+    }
+
+    /// Starts a move relative to the current position.
+    pub fn move_by(&mut self, steps: i64) {
+        self.move_to(self.position + steps);
+    }
+
+    /// Decelerates to a stop as quickly as the acceleration allows.
+    pub fn stop(&mut self) {
+        if self.speed != 0.0 {
+            let steps = self.steps_to_stop() + 1;
+            match self.direction {
+                Direction::CW => self.move_to(self.position + steps),
+                Direction::CCW => self.move_to(self.position - steps),
+            }
+        }
+    }
+
+    /// Returns the current position in steps.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Returns the target position in steps.
+    pub fn target(&self) -> i64 {
+        self.target
+    }
+
+    /// Returns the number of steps remaining until the target is reached.
+    pub fn distance_to_go(&self) -> i64 {
+        self.target - self.position
+    }
+
+    /// Returns the current signed velocity in steps per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Returns the direction of the current or last step.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns `true` while the motor is moving or has not yet reached the target.
+    pub fn is_running(&self) -> bool {
+        self.speed != 0.0 || self.target != self.position
+    }
+
+    /// Redefines the current position without moving the motor.
+    ///
+    /// This also stops any ongoing move; the target is set to the new position.
+    pub fn set_position(&mut self, position: i64) {
+        self.position = position;
+        self.target = position;
+        self.speed = 0.0;
+        self.n = 0;
+    }
+
+    /// Advances the profile by one step and returns its timing.
+    ///
+    /// The returned delay is measured from the previous step (or from the start of the move for
+    /// the first step). The position is updated immediately, so the caller is expected to issue
+    /// the step once the delay has elapsed. Returns `None` when the target has been reached.
+    pub fn next_step_interval(&mut self) -> Option<StepInterval> {
+        if !self.compute_new_speed() {
+            return None;
+        }
+        match self.direction {
+            Direction::CW => self.position += 1,
+            Direction::CCW => self.position -= 1,
+        }
+        Some(StepInterval {
+            delay_ns: (self.cn * 1.0e9) as u32,
+            direction: self.direction,
+        })
+    }
+
+    /// Number of steps needed to decelerate from the current speed to standstill.
+    fn steps_to_stop(&self) -> i64 {
+        ((self.speed * self.speed) / (2.0 * self.acceleration)) as i64
+    }
+
+    /// Computes the interval for the next step. Returns `false` when the move is complete.
+    fn compute_new_speed(&mut self) -> bool {
+        let distance = self.distance_to_go();
+        let steps_to_stop = self.steps_to_stop();
+
+        if distance == 0 && steps_to_stop <= 1 {
+            self.speed = 0.0;
+            self.n = 0;
+            return false;
+        }
+
+        if distance >= 0 {
+            if self.n > 0 {
+                // Accelerating: start decelerating when the target is within braking distance
+                // or when moving away from it.
+                if steps_to_stop >= distance || self.direction == Direction::CCW {
+                    self.n = -steps_to_stop;
+                }
+            } else if self.n < 0 && steps_to_stop < distance && self.direction == Direction::CW {
+                // Decelerating, but far enough away to accelerate again.
+                self.n = -self.n;
+            }
+        } else if self.n > 0 {
+            if steps_to_stop >= -distance || self.direction == Direction::CW {
+                self.n = -steps_to_stop;
+            }
+        } else if self.n < 0 && steps_to_stop < -distance && self.direction == Direction::CCW {
+            self.n = -self.n;
+        }
+
+        if self.n == 0 {
+            // First step from standstill.
+            self.cn = self.c0;
+            self.direction = if distance > 0 {
+                Direction::CW
+            } else {
+                Direction::CCW
+            };
+        } else {
+            let n = self.n as f32;
+            self.cn -= (2.0 * self.cn) / (4.0 * n + 1.0);
+        }
+        if self.cn < self.cmin {
+            self.cn = self.cmin;
+        }
+        self.n += 1;
+
+        self.speed = 1.0 / self.cn;
+        if self.direction == Direction::CCW {
+            self.speed = -self.speed;
+        }
+        true
+    }
+}
+
+impl StepProfile for Motion {
+    fn next_step_interval(&mut self) -> Option<StepInterval> {
+        Motion::next_step_interval(self)
+    }
+}
+
+/// Returns the first step interval in seconds for the given acceleration.
+fn initial_interval(acceleration: f32) -> f32 {
+    0.676 * libm::sqrtf(2.0 / acceleration)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// Runs the profile to completion and returns the issued steps.
+    fn run(motion: &mut Motion) -> Vec<StepInterval> {
+        let mut steps = Vec::new();
+        while let Some(interval) = motion.next_step_interval() {
+            steps.push(interval);
+            assert!(steps.len() < 100_000, "profile does not terminate");
+        }
+        steps
+    }
+
+    fn delays(steps: &[StepInterval]) -> Vec<u32> {
+        steps.iter().map(|step| step.delay_ns).collect()
+    }
+
+    #[test]
+    fn step_count_matches_distance() {
+        let mut motion = Motion::new(2_000.0, 10_000.0);
+        motion.move_to(500);
+        let steps = run(&mut motion);
+        assert_eq!(steps.len(), 500);
+        assert!(steps.iter().all(|step| step.direction == Direction::CW));
+        assert_eq!(motion.position(), 500);
+
+        motion.move_by(-120);
+        assert_eq!(motion.target(), 380);
+        let steps = run(&mut motion);
+        assert_eq!(steps.len(), 120);
+        assert!(steps.iter().all(|step| step.direction == Direction::CCW));
+        assert_eq!(motion.position(), 380);
+        assert_eq!(motion.distance_to_go(), 0);
+    }
+
+    #[test]
+    fn long_move_reaches_max_velocity() {
+        let mut motion = Motion::new(1_000.0, 10_000.0);
+        motion.move_by(1_000);
+        let delays = delays(&run(&mut motion));
+        // The first interval is c0 = 0.676 * sqrt(2 / a).
+        assert_eq!(delays[0], (initial_interval(10_000.0) * 1.0e9) as u32);
+        // Cruising at 1 ms per step in the middle of the move.
+        assert_eq!(delays[500], 1_000_000);
+        assert_eq!(*delays.iter().min().unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn short_move_is_triangular() {
+        // Far below the distance needed to reach max_velocity.
+        let mut motion = Motion::new(100_000.0, 10_000.0);
+        motion.move_by(40);
+        let delays = delays(&run(&mut motion));
+        assert_eq!(delays.len(), 40);
+        let (peak, &fastest) = delays
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, delay)| *delay)
+            .unwrap();
+        assert!((18..=22).contains(&peak), "peak at step {peak}");
+        assert!(fastest > 10_000, "reached max_velocity: {fastest} ns");
+        assert!(delays[..=peak].windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(delays[peak..].windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn stop_decelerates() {
+        let mut motion = Motion::new(1_000.0, 10_000.0);
+        motion.move_to(10_000);
+        for _ in 0..200 {
+            motion.next_step_interval().unwrap();
+        }
+        assert!(libm::fabsf(motion.speed() - 1_000.0) < 0.01);
+        motion.stop();
+        // About 1000^2 / (2 * 10000) = 50 steps to stop, plus the step in flight.
+        let target = motion.target();
+        assert!((250..=251).contains(&target), "stops at {target}");
+        let delays = delays(&run(&mut motion));
+        assert_eq!(delays.len() as i64, target - 200);
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(motion.position(), target);
+        assert!(!motion.is_running());
+    }
+
+    #[test]
+    fn reverses_mid_move() {
+        let mut motion = Motion::new(1_000.0, 10_000.0);
+        motion.move_to(1_000);
+        for _ in 0..200 {
+            motion.next_step_interval().unwrap();
+        }
+        motion.move_to(0);
+        let steps = run(&mut motion);
+        // Decelerates forward first, then turns around exactly once.
+        let turn = steps
+            .iter()
+            .position(|step| step.direction == Direction::CCW)
+            .unwrap();
+        assert!(turn > 0);
+        assert!(steps[turn..]
+            .iter()
+            .all(|step| step.direction == Direction::CCW));
+        assert!(delays(&steps[..turn])
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1]));
+        // The reversal starts from standstill.
+        assert_eq!(
+            steps[turn].delay_ns,
+            (initial_interval(10_000.0) * 1.0e9) as u32
+        );
+        assert_eq!(steps.len(), 2 * turn + 200);
+        assert_eq!(motion.position(), 0);
+    }
+
+    #[test]
+    fn ends_with_none() {
+        let mut motion = Motion::new(1_000.0, 10_000.0);
+        assert_eq!(motion.next_step_interval(), None);
+        motion.move_by(3);
+        assert_eq!(run(&mut motion).len(), 3);
+        assert_eq!(motion.next_step_interval(), None);
+        assert_eq!(motion.next_step_interval(), None);
+        assert_eq!(motion.speed(), 0.0);
+        assert!(!motion.is_running());
+    }
+}
@@ -8,7 +8,7 @@
 /// |---------|----------------|------|---------------------------------|
 /// | 0x00    | GCONF          | 18   | Global Configuration            |
 /// | 0x01    | GSTAT          | 3    | Global Status Flags             |
-/// | 0x04    | IOIN           | 8    | Read Input Pin States           |
+/// | 0x04    | IOIN           | 8+8  | Input Pin States and Version    |
 /// | 0x06    | OTP_PROG       | -    | OTP Memory Programming          |
 /// | 0x07    | OTP_READ       | -    | OTP Read                        |
 /// | 0x08    | FACTORY_CONF   | 5    | Factory Configuration           |
@@ -27,17 +27,22 @@
 /// | 0x14    | TCOOLTHRS    | 20   | CoolStep & StallGuard Threshold       |
 /// | 0x15    | THIGH        | 20   | High Velocity Threshold               |
 ///
-/// ## 2.3 DcStep (0x33)
+/// ## 2.3 Direct Mode (0x2D)
+/// | Address | Name    | Bits | Description                          |
+/// |---------|---------|------|--------------------------------------|
+/// | 0x2D    | XDIRECT | 9+9  | Direct Coil Current Control          |
+///
+/// ## 2.4 DcStep (0x33)
 /// | Address | Name   | Bits | Description                          |
 /// |---------|--------|------|--------------------------------------|
 /// | 0x33    | VDCMIN | 23   | Minimum Velocity for DcStep          |
 ///
-/// ## 2.4 Motor Driver Registers (0x60 - 0x7F)
+/// ## 2.5 Motor Driver Registers (0x60 - 0x7F)
 /// | Address       | Name                  | Bits      | Description                              |
 /// |---------------|-----------------------|-----------|------------------------------------------|
 /// | 0x60–0x67   | MSLUT[0..7]          | 32 x 8    | Microstep Look‑Up Tables                |
 /// | 0x68        | MSLUTSEL              | 32        | LUT Segmentation Definition             |
-/// | 0x69        | MSLUTSTART            | 16        | Start Values for Microstepping           |
+/// | 0x69        | MSLUTSTART            | 8+8       | Start Values for Microstepping           |
 /// | 0x6A        | MSCNT                 | 10        | Microstep Counter                        |
 /// | 0x6B        | MSCURACT              | 9+9       | Actual Motor Phase Currents              |
 /// | 0x6C        | CHOPCONF              | 32        | Chopper and PWM Configuration            |
@@ -53,7 +58,7 @@ use bitfield::bitfield;
 //
 /// Enumeration of TMC2160 registers.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register {
     // General Configuration Registers
     GConf = 0x00,
@@ -75,11 +80,21 @@ pub enum Register {
     TCoolThrs = 0x14,
     THigh = 0x15,
 
+    // Direct Mode
+    XDirect = 0x2D,
+
     // DcStep
     VdcMin = 0x33,
 
     // Motor Driver Registers
-    // MSLUT[0..7] occupy 0x60–0x67.
+    MSLut0 = 0x60,
+    MSLut1 = 0x61,
+    MSLut2 = 0x62,
+    MSLut3 = 0x63,
+    MSLut4 = 0x64,
+    MSLut5 = 0x65,
+    MSLut6 = 0x66,
+    MSLut7 = 0x67,
     MSLutSel = 0x68,
     MSLutStart = 0x69,
     MsCnt = 0x6A,
@@ -94,8 +109,26 @@ pub enum Register {
     LostSteps = 0x73,
 }
 
+impl Register {
+    /// Returns the mask of the bits that read back as written, or `None` if the register cannot
+    /// be verified by reading it back.
+    ///
+    /// Only GCONF, FACTORY_CONF, XDIRECT and CHOPCONF qualify: the configuration registers of the
+    /// TMC2160, GLOBAL_SCALER included, are write only, GSTAT is cleared by writing ones and the
+    /// remaining readable registers are read only. Reserved bits are excluded from the mask.
+    pub fn readback_mask(self) -> Option<u32> {
+        match self {
+            Register::GConf => Some(0x0001_FFFF),
+            Register::FactoryConf => Some(0x0000_001F),
+            Register::XDirect => Some(0x01FF_01FF),
+            Register::ChopConf => Some(0xFFFD_DFFF),
+            _ => None,
+        }
+    }
+}
+
 bitfield! {
-    #[doc = "GConf represents the Global Configuration register (0x00).\n\nThis register contains various global configuration flags:\n\n- Bit 0: recalibrate (Zero‑crossing recalibration)\n- Bit 1: faststandstill (Shortened standstill timeout)\n- Bit 2: en_pwm_mode (Enables StealthChop PWM)\n- Bit 3: multistep_filt (Enables Step Filtering)\n- Bit 4: shaft (Inverts Motor Direction)\n- Bit 5: diag0_error (DIAG0 Active on Errors)\n- Bit 6: diag0_otpw (DIAG0 Active on Overtemperature Warning)\n- Bit 7: diag0_stall (DIAG0 Active on Stall Detection)\n- Bit 8: diag1_stall (DIAG1 Active on Stall Detection)\n- Bit 9: diag1_index (DIAG1 Active on Index Position)\n- Bit 10: diag1_onstate (DIAG1 Active when Chopper is ON)\n- Bit 11: diag1_steps_skipped (DIAG1 Toggles on Missed Steps)\n- Bit 12: diag0_int_pushpull (DIAG0 Push‑Pull Output)\n- Bit 13: diag1_pushpull (DIAG1 Push‑Pull Output)\n- Bit 14: small_hysteresis (Reduces Step Hysteresis)\n- Bit 15: stop_enable (Emergency Stop via DCEN)\n- Bit 16: direct_mode (SPI Direct Coil Current Control)"]
+    #[doc = "GConf represents the Global Configuration register (0x00).\n\nThis register contains various global configuration flags:\n\n- Bit 0: recalibrate (Zero‑crossing recalibration)\n- Bit 1: faststandstill (Shortened standstill timeout)\n- Bit 2: en_pwm_mode (Enables StealthChop PWM)\n- Bit 3: multistep_filt (Enables Step Filtering)\n- Bit 4: shaft (Inverts Motor Direction)\n- Bit 5: diag0_error (DIAG0 Active on Errors)\n- Bit 6: diag0_otpw (DIAG0 Active on Overtemperature Warning)\n- Bit 7: diag0_stall (DIAG0 Active on Stall Detection)\n- Bit 8: diag1_stall (DIAG1 Active on Stall Detection)\n- Bit 9: diag1_index (DIAG1 Active on Index Position)\n- Bit 10: diag1_onstate (DIAG1 Active when Chopper is ON)\n- Bit 11: diag1_steps_skipped (DIAG1 Toggles on Missed Steps)\n- Bit 12: diag0_int_pushpull (DIAG0 Push‑Pull Output)\n- Bit 13: diag1_pushpull (DIAG1 Push‑Pull Output)\n- Bit 14: small_hysteresis (Reduces Step Hysteresis)\n- Bit 15: stop_enable (Emergency Stop via DCIN)\n- Bit 16: direct_mode (SPI Direct Coil Current Control)"]
     #[derive(Clone, Copy)]
     pub struct GConf(u32);
     impl Debug;
@@ -129,16 +162,18 @@ bitfield! {
 }
 
 bitfield! {
-    #[doc = "ChopConf represents the CHOPCONF register (0x6C).\n\nA simplified view of CHOPCONF:\n- TOFF: bits 0–3\n- HSTRT: bits 4–6\n- HEND: bits 7–10\n- TBL: bits 11–12\n- CHM: bit 15\n- MRES: bits 24–27 (microstep resolution)"]
+    #[doc = "ChopConf represents the CHOPCONF register (0x6C).\n\nA simplified view of CHOPCONF:\n- TOFF: bits 0–3\n- HSTRT: bits 4–6\n- HEND: bits 7–10\n- CHM: bit 14 (0 = SpreadCycle, 1 = constant off time)\n- TBL: bits 15–16 (blank time 16, 24, 36 or 54 clocks)\n- MRES: bits 24–27 (microstep resolution)\n- INTPOL: bit 28 (interpolation to 256 microsteps)\n- DEDGE: bit 29 (step on both STEP edges)"]
     #[derive(Clone, Copy)]
     pub struct ChopConf(u32);
     impl Debug;
     pub toff, set_toff: 3, 0;
     pub hstrt, set_hstrt: 6, 4;
     pub hend, set_hend: 10, 7;
-    pub tbl, set_tbl: 12, 11;
-    pub chm, set_chm: 15, 15;
+    pub chm, set_chm: 14;
+    pub tbl, set_tbl: 16, 15;
     pub mres, set_mres: 27, 24;
+    pub intpol, set_intpol: 28;
+    pub dedge, set_dedge: 29;
 }
 
 bitfield! {
@@ -158,17 +193,66 @@ bitfield! {
     pub pwm_freq, set_pwm_freq: 3, 0;
 }
 
-//
-/// GSTAT (Global Status Flags) - Register 0x01 (3 bits)
-#[doc = "GStat wraps the 3‑bit Global Status Flags from register 0x01."]
-#[derive(Debug, Clone, Copy)]
-pub struct GStat(pub u8);
+bitfield! {
+    #[doc = "SpiStatus represents the status byte returned as the first byte of every SPI datagram.\n\n- Bit 0: reset_flag (GSTAT.reset)\n- Bit 1: driver_error (GSTAT.drv_err)\n- Bit 2: sg2 (DRV_STATUS.stallGuard)\n- Bit 3: standstill (DRV_STATUS.stst)\n- Bits 4..=7: always 0"]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct SpiStatus(u8);
+    impl Debug;
+    pub reset_flag, _: 0;
+    pub driver_error, _: 1;
+    pub sg2, _: 2;
+    pub standstill, _: 3;
+    pub unused, _: 7, 4;
+}
 
-//
-/// IOIN (Input Pin States) - Register 0x04 (8 bits)
-#[doc = "IOIn wraps the 8‑bit register used to read input pin states (register 0x04)."]
-#[derive(Debug, Clone, Copy)]
-pub struct IOIn(pub u8);
+bitfield! {
+    #[doc = "GStat represents the GSTAT register (0x01, read / write 1 to clear).\n\n- Bit 0: reset (the IC has been reset since the last clear; all registers are at their defaults)\n- Bit 1: drv_err (the driver was shut down by overtemperature or short circuit detection; see DRV_STATUS)\n- Bit 2: uv_cp (undervoltage on the charge pump; the driver is disabled while it persists)"]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct GStat(u32);
+    impl Debug;
+    pub reset, set_reset: 0;
+    pub drv_err, set_drv_err: 1;
+    pub uv_cp, set_uv_cp: 2;
+}
+
+bitfield! {
+    #[doc = "IOIn represents the IOIN register (0x04, read only).\n\n- Bit 0: REFL_STEP (STEP input level)\n- Bit 1: REFR_DIR (DIR input level)\n- Bit 2: ENCB_DCEN_CFG4 (DCEN input level)\n- Bit 3: ENCA_DCIN_CFG5 (DCIN input level)\n- Bit 4: DRV_ENN (enable input level, high = disabled)\n- Bit 5: ENC_N_DCO_CFG6 (DCO output level)\n- Bit 6: SD_MODE (1 = STEP/DIR mode)\n- Bit 7: SWCOMP_IN (shared with the SW_SEL pin)\n- Bits 24..=31: VERSION (0x30 for the TMC2160)"]
+    #[derive(Clone, Copy)]
+    pub struct IOIn(u32);
+    impl Debug;
+    pub refl_step, _: 0;
+    pub refr_dir, _: 1;
+    pub encb_dcen_cfg4, _: 2;
+    pub enca_dcin_cfg5, _: 3;
+    pub drv_enn, _: 4;
+    pub enc_n_dco_cfg6, _: 5;
+    pub sd_mode, _: 6;
+    pub swcomp_in, _: 7;
+    pub version, _: 31, 24;
+}
+
+bitfield! {
+    #[doc = "ShortConf represents the SHORT_CONF register (0x09, write only).\n\n- Bits 0..=3: S2VS_LEVEL (short to supply sensitivity, 4 = highest … 15 = lowest)\n- Bits 8..=11: S2G_LEVEL (short to ground sensitivity, 2 = highest … 15 = lowest)\n- Bits 16..=17: SHORTFILTER (spike filter, 0 = 100ns … 3 = 3µs)\n- Bit 18: shortdelay (detection delay, 0 = 750ns, 1 = 1500ns)"]
+    #[derive(Clone, Copy)]
+    pub struct ShortConf(u32);
+    impl Debug;
+    pub s2vs_level, set_s2vs_level: 3, 0;
+    pub s2g_level, set_s2g_level: 11, 8;
+    pub shortfilter, set_shortfilter: 17, 16;
+    pub shortdelay, set_shortdelay: 18;
+}
+
+bitfield! {
+    #[doc = "DrvConf represents the DRV_CONF register (0x0A, write only).\n\n- Bits 0..=4: BBMTIME (break before make time, 0..24)\n- Bits 8..=11: BBMCLKS (break before make time in clock cycles)\n- Bits 16..=17: OTSELECT (overtemperature shutdown threshold, see `OtSelect`)\n- Bits 18..=19: DRVSTRENGTH (gate driver current)\n- Bits 20..=21: FILT_ISENSE (sense amplifier filter time constant)"]
+    #[derive(Clone, Copy)]
+    pub struct DrvConf(u32);
+    impl Debug;
+    pub bbmtime, set_bbmtime: 4, 0;
+    pub bbmclks, set_bbmclks: 11, 8;
+    pub otselect, set_otselect: 17, 16;
+    pub drvstrength, set_drvstrength: 19, 18;
+    pub filt_isense, set_filt_isense: 21, 20;
+}
 
 //
 /// OTP_PROG (OTP Memory Programming) - Register 0x06
@@ -188,18 +272,6 @@ pub struct OtpRead(pub u32);
 #[derive(Debug, Clone, Copy)]
 pub struct FactoryConf(pub u8);
 
-//
-/// SHORT_CONF (Short Circuit Detection) - Register 0x09 (19 bits)
-#[doc = "ShortConf wraps the 19‑bit register for short circuit detection (register 0x09)."]
-#[derive(Debug, Clone, Copy)]
-pub struct ShortConf(pub u32);
-
-//
-/// DRV_CONF (Driver Strength and Protection) - Register 0x0A (22 bits)
-#[doc = "DrvConf wraps the 22‑bit register for driver strength and protection (register 0x0A)."]
-#[derive(Debug, Clone, Copy)]
-pub struct DrvConf(pub u32);
-
 //
 /// GLOBAL_SCALER (Current Scaling Factor) - Register 0x0B (8 bits)
 #[doc = "GlobalScaler wraps the 8‑bit current scaling factor register (register 0x0B)."]
@@ -242,11 +314,22 @@ pub struct TCoolThrs(pub u32);
 #[derive(Debug, Clone, Copy)]
 pub struct THigh(pub u32);
 
-//
-/// VDCMIN (Minimum Velocity for DcStep) - Register 0x33 (23 bits)
-#[doc = "VdcMin wraps the 23‑bit register for minimum velocity in DcStep mode (register 0x33)."]
-#[derive(Debug, Clone, Copy)]
-pub struct VdcMin(pub u32);
+bitfield! {
+    #[doc = "XDirect represents the XDIRECT register (0x2D).\n\nIn direct mode (GCONF.direct_mode), the coil currents are set directly instead of by the sequencer:\n- Bits 0..=8: coil A current (signed, -255..=255)\n- Bits 16..=24: coil B current (signed, -255..=255)"]
+    #[derive(Clone, Copy)]
+    pub struct XDirect(u32);
+    impl Debug;
+    pub i16, coil_a, set_coil_a: 8, 0;
+    pub i16, coil_b, set_coil_b: 24, 16;
+}
+
+bitfield! {
+    #[doc = "VdcMin represents the VDCMIN register (0x33, write only).\n\n- Bits 0..=22: VDCMIN (minimum DcStep velocity in 1/256 microsteps per t, t = 2^24 / fCLK)"]
+    #[derive(Clone, Copy)]
+    pub struct VdcMin(u32);
+    impl Debug;
+    pub vdcmin, set_vdcmin: 22, 0;
+}
 
 //
 /// MSLUT - Microstep Look‑Up Table Entries (Registers 0x60 – 0x67)
@@ -254,46 +337,75 @@ pub struct VdcMin(pub u32);
 #[derive(Debug, Clone, Copy)]
 pub struct MSLut(pub u32);
 
-//
-/// MSLUTSEL (LUT Segmentation Definition) - Register 0x68 (32 bits)
-#[doc = "MSLutSel wraps the 32‑bit LUT segmentation definition register (register 0x68)."]
-#[derive(Debug, Clone, Copy)]
-pub struct MSLutSel(pub u32);
+bitfield! {
+    #[doc = "MSLutSel represents the MSLUTSEL register (0x68, write only).\n\nThe quarter-wave table is divided into four segments, each with its own step width control Wx. A MSLUT bit of 0 adds Wx - 1 to the previous entry, a bit of 1 adds Wx.\n- Bits 0..=1, 2..=3, 4..=5, 6..=7: W0..W3 (step width control of segments 0..3)\n- Bits 8..=15: X1 (start of segment 1)\n- Bits 16..=23: X2 (start of segment 2)\n- Bits 24..=31: X3 (start of segment 3)"]
+    #[derive(Clone, Copy)]
+    pub struct MSLutSel(u32);
+    impl Debug;
+    pub w0, set_w0: 1, 0;
+    pub w1, set_w1: 3, 2;
+    pub w2, set_w2: 5, 4;
+    pub w3, set_w3: 7, 6;
+    pub x1, set_x1: 15, 8;
+    pub x2, set_x2: 23, 16;
+    pub x3, set_x3: 31, 24;
+}
 
-//
-/// MSLUTSTART (Start Values for Microstepping) - Register 0x69 (16 bits)
-#[doc = "MSLutStart wraps the 16‑bit start values for microstepping register (register 0x69)."]
-#[derive(Debug, Clone, Copy)]
-pub struct MSLutStart(pub u16);
+bitfield! {
+    #[doc = "MSLutStart represents the MSLUTSTART register (0x69, write only).\n\n- Bits 0..=7: START_SIN (value of table entry 0)\n- Bits 16..=23: START_SIN90 (value of table entry 256, i.e. coil B at MSCNT = 0)"]
+    #[derive(Clone, Copy)]
+    pub struct MSLutStart(u32);
+    impl Debug;
+    pub start_sin, set_start_sin: 7, 0;
+    pub start_sin90, set_start_sin90: 23, 16;
+}
 
-//
-/// MSCNT (Microstep Counter) - Register 0x6A (10 bits)
-#[doc = "MsCnt wraps the 10‑bit microstep counter (register 0x6A)."]
-#[derive(Debug, Clone, Copy)]
-pub struct MsCnt(pub u16);
+bitfield! {
+    #[doc = "MsCnt represents the MSCNT register (0x6A, read only).\n\n- Bits 0..=9: MSCNT (position in the microstep table, 0..=1023 for one electrical cycle)"]
+    #[derive(Clone, Copy)]
+    pub struct MsCnt(u32);
+    impl Debug;
+    pub mscnt, _: 9, 0;
+}
 
-//
-/// MSCURACT (Actual Motor Phase Currents) - Register 0x6B (9+9 bits)
-#[doc = "MsCurAct represents the actual motor phase currents from register 0x6B.\nIt contains two 9‑bit fields for phase A and phase B currents."]
-#[derive(Debug, Clone, Copy)]
-pub struct MsCurAct {
-    /// Current for phase A (9 bits)
-    pub phase_a: u16,
-    /// Current for phase B (9 bits)
-    pub phase_b: u16,
+bitfield! {
+    #[doc = "MsCurAct represents the MSCURACT register (0x6B, read only).\n\nThe values are read from the microstep table and not scaled by the current setting:\n- Bits 0..=8: CUR_A (signed coil A current, -255..=255)\n- Bits 16..=24: CUR_B (signed coil B current, -255..=255)"]
+    #[derive(Clone, Copy)]
+    pub struct MsCurAct(u32);
+    impl Debug;
+    pub i16, cur_a, _: 8, 0;
+    pub i16, cur_b, _: 24, 16;
 }
 
-//
-/// DCCTRL (DcStep Control) - Register 0x6E (24 bits)
-#[doc = "DcCtrl wraps the 24‑bit register for DcStep control (register 0x6E)."]
-#[derive(Debug, Clone, Copy)]
-pub struct DcCtrl(pub u32);
+bitfield! {
+    #[doc = "DcCtrl represents the DCCTRL register (0x6E, write only).\n\n- Bits 0..=9: DC_TIME (upper PWM on time limit for commutation, in clock cycles; set slightly above the effective blank time TBL)\n- Bits 16..=23: DC_SG (maximum PWM on time for step loss detection, in multiples of 16 clock cycles; 0 disables it)"]
+    #[derive(Clone, Copy)]
+    pub struct DcCtrl(u32);
+    impl Debug;
+    pub dc_time, set_dc_time: 9, 0;
+    pub dc_sg, set_dc_sg: 23, 16;
+}
 
-//
-/// DRV_STATUS (Diagnostics and StallGuard2 Feedback) - Register 0x6F (32 bits)
-#[doc = "DrvStatus wraps the 32‑bit diagnostics and StallGuard2 feedback register (register 0x6F)."]
-#[derive(Debug, Clone, Copy)]
-pub struct DrvStatus(pub u32);
+bitfield! {
+    #[doc = "DrvStatus represents the DRV_STATUS register (0x6F, read only).\n\n- Bits 0..=9: SG_RESULT (StallGuard2 result)\n- Bit 12: s2vsa (short to supply, phase A)\n- Bit 13: s2vsb (short to supply, phase B)\n- Bit 14: stealth (StealthChop active)\n- Bit 15: fsactive (full step active)\n- Bits 16..=20: CS_ACTUAL (actual current scale)\n- Bit 24: stallGuard (stall detected)\n- Bit 25: ot (overtemperature shutdown)\n- Bit 26: otpw (overtemperature prewarning)\n- Bit 27: s2ga (short to ground, phase A)\n- Bit 28: s2gb (short to ground, phase B)\n- Bit 29: ola (open load, phase A)\n- Bit 30: olb (open load, phase B)\n- Bit 31: stst (standstill)"]
+    #[derive(Clone, Copy)]
+    pub struct DrvStatus(u32);
+    impl Debug;
+    pub sg_result, _: 9, 0;
+    pub s2vsa, _: 12;
+    pub s2vsb, _: 13;
+    pub stealth, _: 14;
+    pub fsactive, _: 15;
+    pub cs_actual, _: 20, 16;
+    pub stall_guard, _: 24;
+    pub ot, _: 25;
+    pub otpw, _: 26;
+    pub s2ga, _: 27;
+    pub s2gb, _: 28;
+    pub ola, _: 29;
+    pub olb, _: 30;
+    pub stst, _: 31;
+}
 
 //
 /// PWM_SCALE (StealthChop PWM Scaling) - Register 0x71 (9+8 bits)
@@ -307,8 +419,10 @@ pub struct PwmScale(pub u16);
 #[derive(Debug, Clone, Copy)]
 pub struct PwmAuto(pub u16);
 
-//
-/// LOST_STEPS (Step Loss Counter) - Register 0x73 (20 bits)
-#[doc = "LostSteps wraps the 20‑bit step loss counter register (register 0x73)."]
-#[derive(Debug, Clone, Copy)]
-pub struct LostSteps(pub u32);
+bitfield! {
+    #[doc = "LostSteps represents the LOST_STEPS register (0x73, read only).\n\n- Bits 0..=19: LOST_STEPS (input steps skipped in DcStep operation because the motor could not follow; counts up or down with the direction and wraps around at 2^20)"]
+    #[derive(Clone, Copy)]
+    pub struct LostSteps(u32);
+    impl Debug;
+    pub lost_steps, _: 19, 0;
+}
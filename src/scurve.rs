@@ -0,0 +1,498 @@
+//! Jerk-limited (S-curve) motion profiles.
+//!
+//! An `SCurve` plans a rest-to-rest move as the classic 7-segment profile:
+//!
+//! | Segment | Jerk | Description                    |
+//! |---------|------|--------------------------------|
+//! | 1       | +J   | Acceleration ramps up          |
+//! | 2       | 0    | Constant acceleration          |
+//! | 3       | -J   | Acceleration ramps down        |
+//! | 4       | 0    | Cruise at peak velocity        |
+//! | 5       | -J   | Deceleration ramps up          |
+//! | 6       | 0    | Constant deceleration          |
+//! | 7       | +J   | Deceleration ramps down        |
+//!
+//! Short moves drop the constant acceleration and/or cruise segments and use a reduced peak
+//! velocity. Step `k` of a move is issued at the time `t` where the analytic position `s(t)`
+//! equals `k`. The segment boundaries are computed once per move; step times are then found with a
+//! few Newton iterations on the cubic of the current segment, starting from the previous step, and
+//! rounded to whole nanoseconds from the start of the move. Timings are deterministic and can be
+//! checked on the host against `position_at` / `time_at_step`.
+
+use crate::motion::{StepInterval, StepProfile};
+use crate::types::Direction;
+
+/// Maximum number of iterations used to invert the position function. Newton iterations
+/// starting from the previous step typically converge in one to three.
+const MAX_ITERATIONS: u32 = 16;
+
+/// Step size in seconds below which the inversion is considered converged.
+const TIME_TOLERANCE: f64 = 1.0e-11;
+
+/// One segment of constant jerk.
+#[derive(Debug, Clone, Copy, Default)]
+struct Segment {
+    /// Start time of the segment in seconds.
+    start: f64,
+    /// Duration of the segment in seconds.
+    duration: f64,
+    /// Position at the start of the segment in steps.
+    position: f64,
+    /// Velocity at the start of the segment in steps per second.
+    velocity: f64,
+    /// Acceleration at the start of the segment in steps per second squared.
+    acceleration: f64,
+    /// Jerk during the segment in steps per second cubed.
+    jerk: f64,
+}
+
+impl Segment {
+    /// Position at time `t` relative to the start of the segment.
+    fn position_at(&self, t: f64) -> f64 {
+        self.position
+            + self.velocity * t
+            + self.acceleration * t * t / 2.0
+            + self.jerk * t * t * t / 6.0
+    }
+
+    /// Velocity at time `t` relative to the start of the segment.
+    fn velocity_at(&self, t: f64) -> f64 {
+        self.velocity + self.acceleration * t + self.jerk * t * t / 2.0
+    }
+
+    /// Acceleration at time `t` relative to the start of the segment.
+    fn acceleration_at(&self, t: f64) -> f64 {
+        self.acceleration + self.jerk * t
+    }
+
+    /// Returns the time, relative to the start of the segment and no earlier than `from`, at which
+    /// the position reaches `target`.
+    ///
+    /// Position is non-decreasing within a segment, so Newton iterations are kept inside a bracket
+    /// and fall back to bisection where the velocity is zero or a step would leave it.
+    fn time_at(&self, target: f64, from: f64) -> f64 {
+        let (mut lo, mut hi) = (from, self.duration);
+        let velocity = self.velocity_at(lo);
+        let mut t = if velocity > 0.0 {
+            lo + (target - self.position_at(lo)) / velocity
+        } else {
+            (lo + hi) / 2.0
+        };
+        for _ in 0..MAX_ITERATIONS {
+            t = t.clamp(lo, hi);
+            let error = self.position_at(t) - target;
+            if error < 0.0 {
+                lo = t;
+            } else {
+                hi = t;
+            }
+            let velocity = self.velocity_at(t);
+            let step = error / velocity;
+            if velocity > 0.0 && libm::fabs(step) < TIME_TOLERANCE {
+                return t - step;
+            }
+            if hi - lo < TIME_TOLERANCE {
+                return hi;
+            }
+            t -= step;
+            if !(velocity > 0.0 && t > lo && t < hi) {
+                t = (lo + hi) / 2.0;
+            }
+        }
+        t
+    }
+}
+
+/// Jerk-limited motion planner for STEP/DIR driven motors.
+#[derive(Debug, Clone)]
+pub struct SCurve {
+    /// Maximum velocity in steps per second.
+    max_velocity: f64,
+    /// Maximum acceleration in steps per second squared.
+    max_acceleration: f64,
+    /// Maximum jerk in steps per second cubed.
+    max_jerk: f64,
+    /// Segments of the planned move.
+    segments: [Segment; 7],
+    /// Length of the planned move in steps.
+    distance: u64,
+    /// Number of steps issued so far.
+    steps_done: u64,
+    /// Index of the segment containing the last issued step.
+    segment: usize,
+    /// Time of the last issued step in seconds since the start of the move.
+    last_step_time: f64,
+    /// Time of the last issued step in nanoseconds since the start of the move.
+    last_step_ns: u64,
+    /// Current position in steps.
+    position: i64,
+    /// Direction of the planned move.
+    direction: Direction,
+}
+
+impl SCurve {
+    /// Creates a new S-curve planner at position 0.
+    ///
+    /// Limits are given in steps per second, steps per second squared and steps per second cubed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the limits is not positive.
+    pub fn new(max_velocity: f32, max_acceleration: f32, max_jerk: f32) -> Self {
+        assert!(max_velocity > 0.0, "max_velocity must be positive");
+        assert!(max_acceleration > 0.0, "max_acceleration must be positive");
+        assert!(max_jerk > 0.0, "max_jerk must be positive");
+        Self {
+            max_velocity: max_velocity as f64,
+            max_acceleration: max_acceleration as f64,
+            max_jerk: max_jerk as f64,
+            segments: [Segment::default(); 7],
+            distance: 0,
+            steps_done: 0,
+            segment: 0,
+            last_step_time: 0.0,
+            last_step_ns: 0,
+            position: 0,
+            direction: Direction::CW,
+        }
+    }
+
+    /// Plans a move to an absolute position.
+    ///
+    /// Moves are planned from standstill; a move that is still in progress is replaced.
+    pub fn move_to(&mut self, position: i64) {
+        self.move_by(position - self.position);
+    }
+
+    /// Plans a move relative to the current position.
+    ///
+    /// Moves are planned from standstill; a move that is still in progress is replaced.
+    pub fn move_by(&mut self, steps: i64) {
+        self.direction = if steps >= 0 {
+            Direction::CW
+        } else {
+            Direction::CCW
+        };
+        self.distance = steps.unsigned_abs();
+        self.restart();
+    }
+
+    /// Returns the current position in steps.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Redefines the current position without moving the motor. Any planned move is dropped.
+    pub fn set_position(&mut self, position: i64) {
+        self.position = position;
+        self.distance = 0;
+        self.restart();
+    }
+
+    /// Returns `true` while steps of the planned move remain.
+    pub fn is_running(&self) -> bool {
+        self.steps_done < self.distance
+    }
+
+    /// Returns the direction of the planned move.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the total duration of the planned move in seconds.
+    pub fn duration(&self) -> f64 {
+        let last = &self.segments[6];
+        last.start + last.duration
+    }
+
+    /// Returns the peak velocity of the planned move in steps per second.
+    ///
+    /// This is below `max_velocity` for moves that are too short to reach cruise velocity.
+    pub fn peak_velocity(&self) -> f64 {
+        self.segments[3].velocity
+    }
+
+    /// Returns the distance travelled (in steps, unsigned) at time `t` seconds into the move.
+    pub fn position_at(&self, t: f64) -> f64 {
+        let (segment, dt) = self.segment_at(t);
+        segment.position_at(dt)
+    }
+
+    /// Returns the velocity (in steps per second, unsigned) at time `t` seconds into the move.
+    pub fn velocity_at(&self, t: f64) -> f64 {
+        let (segment, dt) = self.segment_at(t);
+        segment.velocity_at(dt)
+    }
+
+    /// Returns the acceleration (in steps per second squared) at time `t` seconds into the move.
+    pub fn acceleration_at(&self, t: f64) -> f64 {
+        let (segment, dt) = self.segment_at(t);
+        segment.acceleration_at(dt)
+    }
+
+    /// Returns the time in seconds at which step `k` (1-based) of the planned move is issued.
+    ///
+    /// This is the time where `position_at` equals `k`.
+    pub fn time_at_step(&self, k: u64) -> f64 {
+        if k == 0 {
+            return 0.0;
+        }
+        if k >= self.distance {
+            return self.duration();
+        }
+        let target = k as f64;
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|s| s.position <= target && s.duration > 0.0)
+            .unwrap_or(&self.segments[0]);
+        segment.start + segment.time_at(target, 0.0)
+    }
+
+    /// Advances the profile by one step and returns its timing.
+    ///
+    /// The returned delay is measured from the previous step (or from the start of the move for
+    /// the first step). Returns `None` when the planned move is complete.
+    pub fn next_step_interval(&mut self) -> Option<StepInterval> {
+        if self.steps_done >= self.distance {
+            return None;
+        }
+        self.steps_done += 1;
+        let time = self.next_step_time();
+        self.last_step_time = time;
+        let time_ns = libm::round(time * 1.0e9) as u64;
+        let delay_ns = time_ns.saturating_sub(self.last_step_ns);
+        self.last_step_ns = time_ns;
+        match self.direction {
+            Direction::CW => self.position += 1,
+            Direction::CCW => self.position -= 1,
+        }
+        Some(StepInterval {
+            delay_ns: delay_ns.min(u32::MAX as u64) as u32,
+            direction: self.direction,
+        })
+    }
+
+    /// Returns the time of step `steps_done`, continuing from the segment and time of the previous
+    /// step. Equivalent to `time_at_step`, without searching the segment table.
+    fn next_step_time(&mut self) -> f64 {
+        if self.steps_done >= self.distance {
+            return self.duration();
+        }
+        let target = self.steps_done as f64;
+        // The segment ends where the next one starts; skip segments ending before the target and
+        // empty ones.
+        while self.segment < 6
+            && (self.segments[self.segment].duration <= 0.0
+                || self.segments[self.segment + 1].position <= target)
+        {
+            self.segment += 1;
+        }
+        let segment = &self.segments[self.segment];
+        let from = (self.last_step_time - segment.start).max(0.0);
+        segment.start + segment.time_at(target, from)
+    }
+
+    /// Resets the step counters and plans the move to `distance`.
+    fn restart(&mut self) {
+        self.steps_done = 0;
+        self.segment = 0;
+        self.last_step_time = 0.0;
+        self.last_step_ns = 0;
+        self.plan();
+    }
+
+    /// Returns the segment active at time `t` and the time relative to its start.
+    fn segment_at(&self, t: f64) -> (&Segment, f64) {
+        let t = t.clamp(0.0, self.duration());
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|s| s.start <= t && s.duration > 0.0)
+            .unwrap_or(&self.segments[0]);
+        (segment, t - segment.start)
+    }
+
+    /// Computes the segment table for the current distance and limits.
+    fn plan(&mut self) {
+        let distance = self.distance as f64;
+        let (jerk, accel) = (self.max_jerk, self.max_acceleration);
+
+        let mut velocity = self.max_velocity;
+        let (mut tj, mut ta) = accel_phase(velocity, accel, jerk);
+        let mut tv = 0.0;
+        let accel_distance = velocity * (2.0 * tj + ta) / 2.0;
+        if 2.0 * accel_distance <= distance {
+            tv = (distance - 2.0 * accel_distance) / velocity;
+        } else {
+            // Cruise velocity is never reached: find the peak velocity for which acceleration and
+            // deceleration together cover exactly the distance.
+            velocity = libm::cbrt(distance * distance * jerk / 4.0);
+            if velocity * jerk > accel * accel {
+                // The acceleration limit is reached as well.
+                velocity = accel / 2.0
+                    * (-accel / jerk
+                        + libm::sqrt(accel * accel / (jerk * jerk) + 4.0 * distance / accel));
+            }
+            (tj, ta) = accel_phase(velocity, accel, jerk);
+        }
+
+        let durations = [tj, ta, tj, tv, tj, ta, tj];
+        let jerks = [jerk, 0.0, -jerk, 0.0, -jerk, 0.0, jerk];
+        let mut state = Segment::default();
+        for (i, segment) in self.segments.iter_mut().enumerate() {
+            state.duration = durations[i];
+            state.jerk = jerks[i];
+            *segment = state;
+            let t = durations[i];
+            state = Segment {
+                start: state.start + t,
+                position: state.position_at(t),
+                velocity: state.velocity_at(t),
+                acceleration: state.acceleration_at(t),
+                ..Segment::default()
+            };
+        }
+    }
+}
+
+impl StepProfile for SCurve {
+    fn next_step_interval(&mut self) -> Option<StepInterval> {
+        SCurve::next_step_interval(self)
+    }
+}
+
+/// Returns the jerk time and constant-acceleration time needed to reach `velocity` from rest.
+fn accel_phase(velocity: f64, accel: f64, jerk: f64) -> (f64, f64) {
+    if velocity * jerk >= accel * accel {
+        let tj = accel / jerk;
+        (tj, velocity / accel - tj)
+    } else {
+        (libm::sqrt(velocity / jerk), 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the planned move and returns (number of steps, sum of the delays in ns, delays of the
+    /// first and last `N` steps).
+    fn run<const N: usize>(curve: &mut SCurve) -> (u64, u64, [u32; N], [u32; N]) {
+        let (mut count, mut total) = (0, 0);
+        let mut delays = [0u32; N];
+        let mut head = [0u32; N];
+        while let Some(interval) = curve.next_step_interval() {
+            if (count as usize) < N {
+                head[count as usize] = interval.delay_ns;
+            }
+            delays[count as usize % N] = interval.delay_ns;
+            count += 1;
+            total += interval.delay_ns as u64;
+        }
+        // Rotate the ring buffer so that the last step comes last.
+        delays.rotate_left(count as usize % N);
+        (count, total, head, delays)
+    }
+
+    /// Step time found by bisection over the whole move, as reference for the Newton iterations.
+    fn bisect(curve: &SCurve, k: u64) -> f64 {
+        let (mut lo, mut hi) = (0.0, curve.duration());
+        for _ in 0..64 {
+            let mid = (lo + hi) / 2.0;
+            if curve.position_at(mid) < k as f64 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        hi
+    }
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            libm::fabs(value - expected) <= tolerance,
+            "{value} differs from {expected} by more than {tolerance}"
+        );
+    }
+
+    #[test]
+    fn jerk_limited_profile() {
+        // Jerk time 0.1 s reaching the acceleration limit exactly; 100 steps per ramp, 800 cruise.
+        let mut curve = SCurve::new(1_000.0, 10_000.0, 100_000.0);
+        curve.move_by(1_000);
+        assert_close(curve.duration(), 1.2, 1e-12);
+        assert_close(curve.peak_velocity(), 1_000.0, 1e-9);
+        assert_close(curve.position_at(0.2), 100.0, 1e-9);
+        assert_close(curve.velocity_at(0.6), 1_000.0, 1e-9);
+        let (count, total, head, tail) = run::<16>(&mut curve);
+        assert_eq!(count, 1_000);
+        assert_eq!(total, 1_200_000_000);
+        assert_eq!(curve.position(), 1_000);
+        // The first step is taken after cbrt(6 / J) seconds.
+        assert_eq!(
+            head[0],
+            libm::round(libm::cbrt(6.0 / 100_000.0) * 1e9) as u32
+        );
+        // Deceleration mirrors acceleration, up to rounding to whole nanoseconds.
+        for (first, last) in head.iter().zip(tail.iter().rev()) {
+            assert!(first.abs_diff(*last) <= 1, "{head:?} {tail:?}");
+        }
+    }
+
+    #[test]
+    fn acceleration_limited_profile() {
+        // Jerk phases of 0.1 s, constant acceleration for 0.1 s, 300 steps per ramp.
+        let mut curve = SCurve::new(2_000.0, 10_000.0, 100_000.0);
+        curve.move_by(-1_000);
+        assert_close(curve.duration(), 0.8, 1e-12);
+        assert_close(curve.peak_velocity(), 2_000.0, 1e-9);
+        assert_close(curve.acceleration_at(0.15), 10_000.0, 1e-9);
+        let (count, total, head, tail) = run::<32>(&mut curve);
+        assert_eq!(count, 1_000);
+        assert_eq!(total, 800_000_000);
+        assert_eq!(curve.position(), -1_000);
+        for (first, last) in head.iter().zip(tail.iter().rev()) {
+            assert!(first.abs_diff(*last) <= 1, "{head:?} {tail:?}");
+        }
+    }
+
+    #[test]
+    fn short_move_profile() {
+        // Too short for cruise or constant acceleration: the peak velocity is cbrt(D² J / 4).
+        let mut curve = SCurve::new(1_000.0, 10_000.0, 100_000.0);
+        curve.move_by(100);
+        let peak = libm::cbrt(100.0 * 100.0 * 100_000.0 / 4.0);
+        assert_close(curve.peak_velocity(), peak, 1e-9);
+        assert_close(curve.duration(), 4.0 * libm::sqrt(peak / 100_000.0), 1e-12);
+        let (count, total, head, tail) = run::<8>(&mut curve);
+        assert_eq!(count, 100);
+        assert_eq!(total, libm::round(curve.duration() * 1e9) as u64);
+        for (first, last) in head.iter().zip(tail.iter().rev()) {
+            assert!(first.abs_diff(*last) <= 1, "{head:?} {tail:?}");
+        }
+    }
+
+    #[test]
+    fn step_times_match_bisection() {
+        for (velocity, distance) in [(1_000.0, 1_000), (2_000.0, 5_000), (1_000.0, 7)] {
+            let mut curve = SCurve::new(velocity, 10_000.0, 100_000.0);
+            curve.move_by(distance);
+            let mut time_ns = 0u64;
+            for k in 1..=distance as u64 {
+                // The last step is issued at the end of the move, where position_at may fall
+                // short of the distance by rounding.
+                let reference = match k == distance as u64 {
+                    true => curve.duration(),
+                    false => bisect(&curve, k),
+                };
+                assert_close(curve.time_at_step(k), reference, 1e-9);
+                time_ns += curve.next_step_interval().unwrap().delay_ns as u64;
+                assert!(time_ns.abs_diff(libm::round(reference * 1e9) as u64) <= 1);
+            }
+            assert_eq!(curve.next_step_interval(), None);
+        }
+    }
+}
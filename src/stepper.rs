@@ -0,0 +1,485 @@
+//! Non-blocking step generation for timer interrupts.
+//!
+//! A `StepperTask` executes a `StepProfile` one edge at a time. A timer interrupt calls `tick()` with
+//! the current time of a monotonic clock (in nanoseconds); `tick()` drives the STEP/DIR pins if an
+//! edge is due and returns the time at which it needs to be called again. STEP pulse high/low
+//! times and the DIR setup/hold times from the driver's `StepTiming` are enforced internally, as
+//! are backlash compensation steps after a direction reversal, so the interrupt handler only needs
+//! to re-arm its timer.
+//!
+//! A `StepGuard` such as `LimitSwitches` can be attached with `StepperTask::with_guard`; it is
+//! checked before every step and stops the task when it reports an event. An emergency stop on the
+//! driver (`Tmc2160::emergency_stop`) stops the task the same way.
+//!
+//! The task is `no_std` and allocation free. Since the time is passed in by the caller, the task can
+//! be exercised on the host by simulating the clock.
+//!
+//! ```ignore
+//! // In the timer interrupt:
+//! match task.tick(&mut driver, now_ns())? {
+//!     Some(deadline) => timer.schedule_at(deadline),
+//!     None => timer.stop(), // Profile complete or stopped, see `task.event()`.
+//! }
+//! ```
+
+use crate::motion::{MotionEvent, StepGuard, StepProfile};
+use crate::tmc2160::Tmc2160;
+use crate::types::{Direction, Error, SteppingMode};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Phase of the step currently being generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// No step is scheduled; the next one is fetched from the profile.
+    Idle,
+    /// The next step reverses direction; DIR changes at the deadline, after the hold time.
+    DirHold,
+    /// A step is scheduled; STEP rises at the deadline.
+    StepDue,
+    /// STEP is high; it falls at the deadline.
+    StepHigh,
+    /// Motion was stopped by the guard or an emergency stop; the task stays stopped until
+    /// `resume()`.
+    Stopped,
+}
+
+/// Interrupt-driven executor for a `StepProfile`.
+#[derive(Debug)]
+pub struct StepperTask<P, G = ()> {
+    profile: P,
+    /// Check performed before every step.
+    guard: G,
+    phase: Phase,
+    /// Event that ended the last move.
+    event: Option<MotionEvent>,
+    /// Time at which the next edge is due, in nanoseconds.
+    deadline: u64,
+    /// Scheduled time of the last rising STEP edge, used as reference for the next interval.
+    last_step: Option<u64>,
+    /// Time at which the next step is due according to the profile.
+    step_due: u64,
+    /// Direction of the next step.
+    direction: Direction,
+    /// Whether the current step is an uncounted backlash compensation step.
+    compensating: bool,
+}
+
+impl<P: StepProfile> StepperTask<P> {
+    /// Creates a new task executing the given profile.
+    pub fn new(profile: P) -> Self {
+        Self::with_guard(profile, ())
+    }
+}
+
+impl<P: StepProfile, G> StepperTask<P, G> {
+    /// Creates a new task executing the given profile, checking `guard` before every step.
+    pub fn with_guard(profile: P, guard: G) -> Self {
+        Self {
+            profile,
+            guard,
+            phase: Phase::Idle,
+            event: None,
+            deadline: 0,
+            last_step: None,
+            step_due: 0,
+            direction: Direction::CW,
+            compensating: false,
+        }
+    }
+
+    /// Returns a mutable reference to the guard.
+    pub fn guard_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+
+    /// Returns the event that ended the last move, once `tick()` has returned `Ok(None)`.
+    pub fn event(&self) -> Option<MotionEvent> {
+        self.event
+    }
+
+    /// Returns `true` if motion was stopped by the guard or an emergency stop.
+    pub fn is_stopped(&self) -> bool {
+        self.phase == Phase::Stopped
+    }
+
+    /// Clears a stop reported by the guard or an emergency stop so that the next `tick()`
+    /// continues with the profile.
+    ///
+    /// The step that was refused has already been consumed from the profile. Profiles that track
+    /// a position (e.g. `Motion`) should be re-synchronized with `Tmc2160::position` before
+    /// starting a new move.
+    pub fn resume(&mut self) {
+        if self.phase == Phase::Stopped {
+            self.phase = Phase::Idle;
+        }
+    }
+
+    /// Returns a reference to the executed profile.
+    pub fn profile(&self) -> &P {
+        &self.profile
+    }
+
+    /// Returns a mutable reference to the executed profile, e.g. to start a new move.
+    ///
+    /// A new move on an idle task starts at the next call to `tick()`.
+    pub fn profile_mut(&mut self) -> &mut P {
+        &mut self.profile
+    }
+
+    /// Consumes the task and returns the profile.
+    pub fn into_profile(self) -> P {
+        self.profile
+    }
+
+    /// Returns `true` if no step is currently scheduled or in progress.
+    pub fn is_idle(&self) -> bool {
+        self.phase == Phase::Idle
+    }
+
+    /// Drives the STEP/DIR pins and returns the time at which `tick()` must be called next.
+    ///
+    /// `now` is the current time of a monotonic clock in nanoseconds. Calling `tick()` before the
+    /// returned deadline is harmless; the deadline is returned again. Returns `Ok(None)` once the
+    /// profile is complete or the guard stopped motion; `event()` tells which.
+    pub fn tick<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+    ) -> Result<Option<u64>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        G: StepGuard<PinE>,
+    {
+        let timing = driver.step_timing();
+        match self.phase {
+            Phase::Idle => self.schedule(driver, now, now),
+            Phase::Stopped => Ok(None),
+            Phase::DirHold if now < self.deadline => Ok(Some(self.deadline)),
+            Phase::DirHold => {
+                driver.set_dir_pin(self.direction)?;
+                let due = self.step_due.max(now + timing.dir_setup_ns as u64);
+                self.step_at(driver, now, due)
+            }
+            Phase::StepDue if now < self.deadline => Ok(Some(self.deadline)),
+            Phase::StepDue => self.rise(driver, now),
+            Phase::StepHigh if now < self.deadline => Ok(Some(self.deadline)),
+            Phase::StepHigh => {
+                driver.set_step_pin(false)?;
+                self.finish_step(driver, now, now + timing.min_low_ns as u64)
+            }
+        }
+    }
+
+    /// Fetches the next step from the profile and schedules its rising edge no earlier than
+    /// `earliest`.
+    fn schedule<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+        earliest: u64,
+    ) -> Result<Option<u64>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        G: StepGuard<PinE>,
+    {
+        let Some(interval) = self.profile.next_step_interval() else {
+            self.phase = Phase::Idle;
+            self.last_step = None;
+            self.event = Some(MotionEvent::Completed);
+            return Ok(None);
+        };
+        self.event = None;
+        let timing = driver.step_timing();
+        let due = (self.last_step.unwrap_or(now) + interval.delay_ns as u64).max(earliest);
+        if driver.direction() == interval.direction {
+            return self.step_at(driver, now, due);
+        }
+        self.direction = interval.direction;
+        self.step_due = due;
+        // The driver keeps the last step edge across moves, so the hold time is also observed
+        // when a new move starts in the opposite direction.
+        let hold_until = driver.dir_hold_until(now);
+        if hold_until > now {
+            self.phase = Phase::DirHold;
+            self.deadline = hold_until;
+            return Ok(Some(hold_until));
+        }
+        driver.set_dir_pin(interval.direction)?;
+        self.step_at(driver, now, due.max(now + timing.dir_setup_ns as u64))
+    }
+
+    /// Schedules the rising STEP edge at `due`, or raises STEP immediately if it is already due.
+    fn step_at<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+        due: u64,
+    ) -> Result<Option<u64>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        G: StepGuard<PinE>,
+    {
+        self.phase = Phase::StepDue;
+        self.deadline = due;
+        if due > now {
+            Ok(Some(due))
+        } else {
+            self.rise(driver, now)
+        }
+    }
+
+    /// Issues the scheduled step: raises STEP, or toggles it in double-edge mode.
+    ///
+    /// An active emergency stop and the guard are checked first; if either reports an event, the
+    /// step is not issued.
+    fn rise<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+    ) -> Result<Option<u64>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        G: StepGuard<PinE>,
+    {
+        let event = if driver.is_emergency_stopped() {
+            Some(MotionEvent::EmergencyStop)
+        } else {
+            self.guard.check(driver.direction()).map_err(Error::Pin)?
+        };
+        if let Some(event) = event {
+            self.phase = Phase::Stopped;
+            self.last_step = None;
+            self.event = Some(event);
+            return Ok(None);
+        }
+        let timing = driver.step_timing();
+        // After a direction reversal, backlash compensation steps are issued before the step
+        // scheduled by the profile.
+        self.compensating = driver.take_backlash_step();
+        if !self.compensating {
+            // Reference the profile timing to the scheduled time, so interrupt latency does not
+            // accumulate over a move.
+            self.last_step = Some(self.deadline);
+        }
+        driver.record_step_edge(now);
+        match driver.stepping_mode() {
+            SteppingMode::SingleEdge => {
+                driver.set_step_pin(true)?;
+                self.phase = Phase::StepHigh;
+                self.deadline = now + timing.min_high_ns as u64;
+                Ok(Some(self.deadline))
+            }
+            SteppingMode::DoubleEdge => {
+                // Every edge is a step: toggle and hold the new level for its minimum time.
+                let level = driver.toggle_step_pin()?;
+                let hold = if level {
+                    timing.min_high_ns
+                } else {
+                    timing.min_low_ns
+                };
+                self.finish_step(driver, now, now + hold.max(1) as u64)
+            }
+        }
+    }
+
+    /// Accounts for a completed step and schedules the next one no earlier than `earliest`.
+    ///
+    /// After a compensation step, the step scheduled by the profile is retried once the backlash
+    /// step interval has elapsed.
+    fn finish_step<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+        earliest: u64,
+    ) -> Result<Option<u64>, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+        G: StepGuard<PinE>,
+    {
+        if self.compensating {
+            driver.advance_backlash();
+            let due = now + driver.backlash().step_interval_ns as u64;
+            return self.step_at(driver, now, due.max(earliest));
+        }
+        driver.advance_position();
+        self.schedule(driver, now, earliest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::{Bench, Sequence, Steps};
+    use crate::motion::StepInterval;
+    use crate::types::StepTiming;
+    use std::vec;
+
+    const TIMING: StepTiming = StepTiming {
+        min_high_ns: 100,
+        min_low_ns: 100,
+        dir_setup_ns: 50,
+        dir_hold_ns: 5_000,
+    };
+
+    fn bench(mode: SteppingMode) -> Bench {
+        let mut bench = Bench::new();
+        bench.driver.set_step_timing(TIMING);
+        bench.driver.set_stepping_mode(mode).unwrap();
+        bench
+    }
+
+    fn interval(delay_ns: u32, direction: Direction) -> StepInterval {
+        StepInterval {
+            delay_ns,
+            direction,
+        }
+    }
+
+    #[test]
+    fn single_edge_deadlines() {
+        let mut bench = bench(SteppingMode::SingleEdge);
+        let deadlines = bench.run_task(&mut StepperTask::new(Steps::new(3, 1_000, Direction::CW)));
+        assert_eq!(deadlines, [1_000, 1_100, 2_000, 2_100, 3_000, 3_100]);
+        assert_eq!(bench.step_edges(false), [1_000, 2_000, 3_000]);
+        assert_eq!(bench.pulse_widths(), (vec![100; 3], vec![900; 2]));
+        assert_eq!(bench.driver.position(), 3);
+    }
+
+    #[test]
+    fn double_edge_deadlines() {
+        let mut bench = bench(SteppingMode::DoubleEdge);
+        let deadlines = bench.run_task(&mut StepperTask::new(Steps::new(3, 1_000, Direction::CW)));
+        assert_eq!(deadlines, [1_000, 2_000, 3_000]);
+        assert_eq!(bench.step_edges(true), [1_000, 2_000, 3_000]);
+        assert_eq!(bench.driver.position(), 3);
+    }
+
+    #[test]
+    fn blocking_run_matches_task() {
+        for mode in [SteppingMode::SingleEdge, SteppingMode::DoubleEdge] {
+            let mut bench = bench(mode);
+            bench
+                .driver
+                .run(&mut Steps::new(3, 1_000, Direction::CW))
+                .unwrap();
+            let double_edge = mode == SteppingMode::DoubleEdge;
+            assert_eq!(bench.step_edges(double_edge), [1_000, 2_000, 3_000]);
+            assert_eq!(bench.driver.position(), 3);
+        }
+    }
+
+    #[test]
+    fn pulse_widths_at_short_intervals() {
+        for mode in [SteppingMode::SingleEdge, SteppingMode::DoubleEdge] {
+            let mut bench = bench(mode);
+            let mut task = StepperTask::new(Steps::new(10, 30, Direction::CW));
+            bench.run_task(&mut task);
+            assert!(task.is_idle());
+            let (high, low) = bench.pulse_widths();
+            assert!(high.iter().all(|&width| width >= 100), "{high:?}");
+            assert!(low.iter().all(|&width| width >= 100), "{low:?}");
+            let edges = bench.step_edges(mode == SteppingMode::DoubleEdge);
+            assert_eq!(edges.len(), 10);
+        }
+    }
+
+    #[test]
+    fn dir_setup_and_hold() {
+        let mut bench = bench(SteppingMode::SingleEdge);
+        let profile = Sequence(vec![
+            interval(1_000, Direction::CW),
+            interval(1_000, Direction::CW),
+            interval(100, Direction::CCW),
+            interval(1_000, Direction::CCW),
+        ]);
+        let deadlines = bench.run_task(&mut StepperTask::new(profile));
+        // DIR changes 5000 ns after the rise at 2000 and the step follows 50 ns later; the next
+        // interval is referenced to that step.
+        assert_eq!(
+            deadlines,
+            [1_000, 1_100, 2_000, 2_100, 7_000, 7_050, 7_150, 8_050, 8_150]
+        );
+        assert_eq!(bench.dir_changes(), [7_000]);
+        assert_eq!(bench.step_edges(false), [1_000, 2_000, 7_050, 8_050]);
+        assert_eq!(bench.driver.position(), 0);
+        bench.assert_dir_timing(false, 50, 5_000);
+    }
+
+    #[test]
+    fn early_tick_returns_deadline() {
+        let mut bench = bench(SteppingMode::SingleEdge);
+        let mut task = StepperTask::new(Steps::new(1, 1_000, Direction::CW));
+        let mut tick = |now| {
+            bench.clock.set(now);
+            task.tick(&mut bench.driver, now).unwrap()
+        };
+        assert_eq!(tick(0), Some(1_000));
+        assert_eq!(tick(500), Some(1_000));
+        assert_eq!(tick(1_000), Some(1_100));
+        assert_eq!(tick(1_050), Some(1_100));
+        assert_eq!(tick(1_100), None);
+        assert!(task.is_idle());
+        assert_eq!(bench.step_edges(false), [1_000]);
+        assert_eq!(bench.pulse_widths(), (vec![100], vec![]));
+    }
+
+    #[test]
+    fn dir_hold_across_tasks() {
+        for mode in [SteppingMode::SingleEdge, SteppingMode::DoubleEdge] {
+            let mut bench = bench(mode);
+            bench.run_task(&mut StepperTask::new(Steps::new(3, 1_000, Direction::CW)));
+            bench.run_task(&mut StepperTask::new(Steps::new(3, 0, Direction::CCW)));
+            assert_eq!(bench.dir_changes().len(), 1);
+            assert_eq!(bench.driver.position(), 0);
+            bench.assert_dir_timing(mode == SteppingMode::DoubleEdge, 50, 5_000);
+        }
+    }
+
+    #[test]
+    fn dir_hold_across_blocking_moves() {
+        for mode in [SteppingMode::SingleEdge, SteppingMode::DoubleEdge] {
+            let mut bench = bench(mode);
+            bench
+                .driver
+                .run(&mut Steps::new(3, 1_000, Direction::CW))
+                .unwrap();
+            bench
+                .driver
+                .run(&mut Steps::new(3, 0, Direction::CCW))
+                .unwrap();
+            bench.driver.step().unwrap();
+            bench.run_task(&mut StepperTask::new(Steps::new(1, 0, Direction::CW)));
+            assert_eq!(bench.dir_changes().len(), 2);
+            bench.assert_dir_timing(mode == SteppingMode::DoubleEdge, 50, 5_000);
+        }
+    }
+}
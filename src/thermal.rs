@@ -0,0 +1,245 @@
+//! Overtemperature management with automatic current derating.
+//!
+//! DRV_STATUS reports the overtemperature prewarning (otpw, 120°C) and the overtemperature
+//! shutdown (ot, selected with `Tmc2160::set_ot_select`). A `ThermalPolicy` polls these flags and
+//! reduces the run current (IRUN) stepwise while the prewarning is active, giving the driver a
+//! chance to cool down before it shuts down. Once the prewarning has been clear for the
+//! hysteresis time, the run current in effect before derating is restored. Every change is
+//! reported as a `ThermalEvent` through a callback.
+//!
+//! IRUN is changed through the cached IHOLD_IRUN value, so IHOLD and IHOLDDELAY are kept. A run
+//! current set by the application while derating is active is overwritten on restore.
+//!
+//! ```ignore
+//! driver.set_ot_select(OtSelect::Celsius143)?;
+//! let mut thermal = ThermalPolicy::new(ThermalConfig::default(), |event| log_thermal(event));
+//! // Periodically, e.g. from the main loop:
+//! thermal.poll(&mut driver, now_ns())?;
+//! ```
+
+use crate::registers::{DrvStatus, Register};
+use crate::tmc2160::Tmc2160;
+use crate::types::Error;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiBus;
+
+/// Settings of a `ThermalPolicy`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThermalConfig {
+    /// IRUN reduction per derating step.
+    pub step: u8,
+    /// Lowest IRUN the current is derated to.
+    pub min_irun: u8,
+    /// Minimum time between derating steps in nanoseconds.
+    pub interval_ns: u64,
+    /// Time the prewarning must be clear before the current is restored, in nanoseconds.
+    pub hysteresis_ns: u64,
+}
+
+impl Default for ThermalConfig {
+    /// Derates by 2 every second down to IRUN 8 and restores after 10 seconds without prewarning.
+    fn default() -> Self {
+        Self {
+            step: 2,
+            min_irun: 8,
+            interval_ns: 1_000_000_000,
+            hysteresis_ns: 10_000_000_000,
+        }
+    }
+}
+
+/// A change of the thermal state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThermalEvent {
+    /// The prewarning is active and the run current was reduced.
+    Derated {
+        /// The new run current.
+        irun: u8,
+    },
+    /// The prewarning has cleared and the run current was restored.
+    Restored {
+        /// The restored run current.
+        irun: u8,
+    },
+    /// The driver has shut down due to overtemperature; the tracked position is marked uncertain.
+    Shutdown,
+}
+
+/// Derates the run current on overtemperature prewarning.
+#[derive(Debug)]
+pub struct ThermalPolicy<F> {
+    /// Callback invoked for every thermal event.
+    on_event: F,
+    config: ThermalConfig,
+    /// Run current before derating; `Some` while derated.
+    nominal: Option<u8>,
+    /// Time at which the next derating step is due.
+    next_step: Option<u64>,
+    /// Time since which the prewarning has been clear while derated.
+    clear_since: Option<u64>,
+    /// Whether an overtemperature shutdown has been reported.
+    shutdown: bool,
+}
+
+impl<F: FnMut(ThermalEvent)> ThermalPolicy<F> {
+    /// Creates a policy with the given settings.
+    pub fn new(config: ThermalConfig, on_event: F) -> Self {
+        Self {
+            on_event,
+            config,
+            nominal: None,
+            next_step: None,
+            clear_since: None,
+            shutdown: false,
+        }
+    }
+
+    /// Returns `true` while the run current is derated.
+    pub fn is_derated(&self) -> bool {
+        self.nominal.is_some()
+    }
+
+    /// Returns the run current to be restored, if derated.
+    pub fn nominal_irun(&self) -> Option<u8> {
+        self.nominal
+    }
+
+    /// Reads DRV_STATUS and applies the policy.
+    ///
+    /// `now` is the current time of a monotonic clock in nanoseconds. While otpw is set, IRUN is
+    /// reduced by `step` every `interval_ns`, down to `min_irun`. After otpw has been clear for
+    /// `hysteresis_ns`, the original IRUN is restored. An overtemperature shutdown is reported
+    /// once per occurrence. Returns the run current now in effect.
+    pub fn poll<SPI, CS, EN, DIR, STEP, D, SpiE, PinE>(
+        &mut self,
+        driver: &mut Tmc2160<SPI, CS, EN, DIR, STEP, D>,
+        now: u64,
+    ) -> Result<u8, Error<SpiE, PinE>>
+    where
+        SPI: SpiBus<u8, Error = SpiE>,
+        CS: OutputPin<Error = PinE>,
+        EN: OutputPin<Error = PinE>,
+        DIR: OutputPin<Error = PinE>,
+        STEP: OutputPin<Error = PinE>,
+        D: DelayNs,
+    {
+        let status = DrvStatus(driver.read_register(Register::DrvStatus)?);
+        if status.ot() && !self.shutdown {
+            driver.mark_position_uncertain();
+            (self.on_event)(ThermalEvent::Shutdown);
+        }
+        self.shutdown = status.ot();
+
+        let irun = driver.run_current();
+        if status.otpw() {
+            self.clear_since = None;
+            if self.next_step.is_some_and(|next| now < next) {
+                return Ok(irun);
+            }
+            self.next_step = Some(now + self.config.interval_ns);
+            let derated = irun
+                .saturating_sub(self.config.step)
+                .max(self.config.min_irun);
+            if derated >= irun {
+                return Ok(irun);
+            }
+            self.nominal.get_or_insert(irun);
+            driver.set_run_current(derated)?;
+            (self.on_event)(ThermalEvent::Derated { irun: derated });
+            return Ok(derated);
+        }
+
+        self.next_step = None;
+        let Some(nominal) = self.nominal else {
+            return Ok(irun);
+        };
+        let clear_since = *self.clear_since.get_or_insert(now);
+        if now.saturating_sub(clear_since) < self.config.hysteresis_ns {
+            return Ok(irun);
+        }
+        driver.set_run_current(nominal)?;
+        self.nominal = None;
+        self.clear_since = None;
+        (self.on_event)(ThermalEvent::Restored { irun: nominal });
+        Ok(nominal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::Bench;
+    use crate::registers::IHoldIrun;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    const OT: u32 = 1 << 25;
+    const OTPW: u32 = 1 << 26;
+
+    const CONFIG: ThermalConfig = ThermalConfig {
+        step: 2,
+        min_irun: 8,
+        interval_ns: 1_000,
+        hysteresis_ns: 5_000,
+    };
+
+    #[test]
+    fn derates_stepwise_and_restores_after_hysteresis() {
+        let mut bench = Bench::new();
+        bench.driver.set_current(13, 5, 4).unwrap();
+        let events = RefCell::new(Vec::new());
+        let mut thermal = ThermalPolicy::new(CONFIG, |event| events.borrow_mut().push(event));
+        let mut poll = |bench: &mut Bench, drv_status: u32, now: u64| {
+            bench.spi.set_reg(Register::DrvStatus as u8, drv_status);
+            thermal.poll(&mut bench.driver, now).unwrap()
+        };
+
+        assert_eq!(poll(&mut bench, OTPW, 0), 11);
+        assert_eq!(poll(&mut bench, OTPW, 500), 11);
+        assert_eq!(poll(&mut bench, OTPW, 1_000), 9);
+        // Floored at min_irun.
+        assert_eq!(poll(&mut bench, OTPW, 2_000), 8);
+        assert_eq!(poll(&mut bench, OTPW, 3_000), 8);
+        // A prewarning within the hysteresis time restarts it.
+        assert_eq!(poll(&mut bench, 0, 4_000), 8);
+        assert_eq!(poll(&mut bench, OTPW, 6_000), 8);
+        assert_eq!(poll(&mut bench, 0, 7_000), 8);
+        assert_eq!(poll(&mut bench, 0, 11_999), 8);
+        assert_eq!(poll(&mut bench, 0, 12_000), 13);
+        assert_eq!(poll(&mut bench, 0, 13_000), 13);
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                ThermalEvent::Derated { irun: 11 },
+                ThermalEvent::Derated { irun: 9 },
+                ThermalEvent::Derated { irun: 8 },
+                ThermalEvent::Restored { irun: 13 },
+            ]
+        );
+        // IHOLD and IHOLDDELAY are kept.
+        let ihold_irun = IHoldIrun(bench.spi.reg(Register::IHoldIrun as u8));
+        assert_eq!(ihold_irun.ihold(), 5);
+        assert_eq!(ihold_irun.iholddelay(), 4);
+        assert_eq!(ihold_irun.irun(), 13);
+    }
+
+    #[test]
+    fn reports_shutdown_once() {
+        let mut bench = Bench::new();
+        let events = RefCell::new(Vec::new());
+        let mut thermal = ThermalPolicy::new(CONFIG, |event| events.borrow_mut().push(event));
+        for (now, drv_status) in [(0, OT), (1_000, OT), (2_000, 0), (3_000, OT)] {
+            bench.spi.set_reg(Register::DrvStatus as u8, drv_status);
+            thermal.poll(&mut bench.driver, now).unwrap();
+        }
+        assert_eq!(
+            *events.borrow(),
+            [ThermalEvent::Shutdown, ThermalEvent::Shutdown]
+        );
+        assert!(bench.driver.is_position_uncertain());
+    }
+}
@@ -8,13 +8,46 @@
 //! SPI transfers are 40 bits (8‑bit address + 32‑bit data). Write operations require the address MSB
 //! set (i.e. address | 0x80), while reads use the raw address. A register cache is maintained to track
 //! write‑only registers.
+//!
+//! The driver also tracks the absolute motor position. Internally the position is kept in 1/256
+//! microsteps (the resolution of the MSCNT microstep counter), so it is unaffected by changes of the
+//! microstep resolution and can be compared against MSCNT to detect missed pulses.
 
-use crate::registers::{ChopConf, IHoldIrun, Register};
-use crate::types::{Direction, DriverStatus, Error, MicrostepResolution, RegisterCache};
+use crate::direct::MAX_COIL_CURRENT;
+use crate::estop::StopInput;
+use crate::gcode::GCodeAxis;
+use crate::limits::{Homing, Limit, LimitSwitches};
+use crate::motion::{MotionEvent, StepDir, StepGuard, StepProfile};
+use crate::registers::{
+    ChopConf, DcCtrl, DrvConf, DrvStatus, GConf, GStat, IHoldIrun, IOIn, MsCnt, MsCurAct, Register,
+    ShortConf, SpiStatus, VdcMin, XDirect,
+};
+use crate::stepper::StepperTask;
+use crate::types::{
+    Backlash, DcStepConfig, Direction, DriverStatus, Error, InvalidArgument, LinkFault,
+    MicrostepResolution, OtSelect, RegisterCache, StepTiming, SteppingMode,
+};
+use crate::waveform::MicrostepTable;
+use crate::wiring::{WiringReport, WiringTest};
 use embedded_hal::delay::DelayNs;
-use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal::spi::SpiBus;
 
+/// Value of IOIN.VERSION for the TMC2160.
+const TMC2160_VERSION: u8 = 0x30;
+
+/// Complementary patterns for the link self-test, within the implemented bits of XDIRECT.
+const LINK_TEST_PATTERNS: [u32; 2] = [0x0155_00AA, 0x00AA_0155];
+
+/// Blank time in clock cycles for CHOPCONF.TBL = 0..=3.
+const BLANK_TIME_CLOCKS: [u32; 4] = [16, 24, 36, 54];
+
+/// Full scale voltage of the sense resistor comparators (VFS) in mV.
+const VFS_MV: f32 = 325.0;
+
+/// Default sense resistor value in milliohms.
+const DEFAULT_SENSE_RESISTOR_MOHM: u16 = 75;
+
 /// Main driver structure for the TMC2160.
 pub struct Tmc2160<SPI, CS, EN, DIR, STEP, D> {
     spi: SPI,
@@ -25,6 +58,53 @@ pub struct Tmc2160<SPI, CS, EN, DIR, STEP, D> {
     delay: D,
     /// Cache for write‑only registers.
     pub register_cache: RegisterCache,
+    /// Absolute position in 1/256 microsteps.
+    position: i64,
+    /// Current state of the DIR pin.
+    direction: Direction,
+    /// Currently configured microstep resolution.
+    microsteps: MicrostepResolution,
+    /// MSCNT value corresponding to position 0 (modulo 1024).
+    mscnt_offset: u16,
+    /// STEP/DIR interface timing.
+    step_timing: StepTiming,
+    /// STEP input mode (CHOPCONF.dedge).
+    stepping_mode: SteppingMode,
+    /// Current logical level of the STEP pin.
+    step_level: bool,
+    /// Sense resistor value in milliohms.
+    sense_resistor_mohm: u16,
+    /// Backlash compensation settings.
+    backlash: Backlash,
+    /// Status byte received with the last SPI datagram.
+    spi_status: SpiStatus,
+    /// Direction of the last counted step, if any.
+    last_step_direction: Option<Direction>,
+    /// Number of compensation steps still to be issued before the next counted step.
+    pending_backlash: u32,
+    /// Sum of the uncounted compensation steps in 1/256 microsteps.
+    backlash_offset: i64,
+    /// Whether GCONF.stop_enable is set, making DCIN an emergency stop input.
+    stop_enabled: bool,
+    /// Whether an emergency stop is active; no steps are issued while set.
+    emergency_stopped: bool,
+    /// Whether the position may be wrong after an emergency stop.
+    position_uncertain: bool,
+    /// Number of write retries in write verification mode, or `None` if writes are not verified.
+    write_verify: Option<u8>,
+    /// Last STEP edge that issued a step since DIR changed, for the DIR hold time.
+    last_step_edge: StepEdge,
+}
+
+/// Time of the last STEP edge that issued a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepEdge {
+    /// No step since the last direction change.
+    None,
+    /// Issued by a `StepperTask` at the given time of its clock, in nanoseconds.
+    At(u64),
+    /// Issued by `step`, whose time is not known.
+    Unknown,
 }
 
 impl<SPI, CS, EN, DIR, STEP, D, SpiE, PinE> Tmc2160<SPI, CS, EN, DIR, STEP, D>
@@ -60,13 +140,33 @@ where
             step,
             delay,
             register_cache: RegisterCache::default(),
+            position: 0,
+            direction: Direction::CW,
+            microsteps: MicrostepResolution::TwoFiftySixth,
+            mscnt_offset: 0,
+            step_timing: StepTiming::default(),
+            last_step_edge: StepEdge::None,
+            stepping_mode: SteppingMode::SingleEdge,
+            step_level: false,
+            sense_resistor_mohm: DEFAULT_SENSE_RESISTOR_MOHM,
+            backlash: Backlash::default(),
+            spi_status: SpiStatus(0),
+            last_step_direction: None,
+            pending_backlash: 0,
+            backlash_offset: 0,
+            stop_enabled: false,
+            emergency_stopped: false,
+            position_uncertain: false,
+            write_verify: None,
         })
     }
 
     /// Initializes the TMC2160 with default safe configuration settings.
     ///
-    /// This should be called after construction and before enabling the driver.
+    /// This should be called after construction and before enabling the driver. The chip is
+    /// identified first (see `identify`), so no registers are written to a different device.
     pub fn init(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        self.identify()?;
         // Set default current: run current 16, hold current 8, hold delay 4.
         self.set_current(16, 8, 4)?;
         // Set default microstepping (Full step).
@@ -75,48 +175,175 @@ where
         let mut chopconf = self.read_chopconf()?;
         chopconf.set_toff(5);
         self.write_chopconf(chopconf)?;
+        // Align the position counter with the microstep counter.
+        self.set_position(0)?;
         Ok(())
     }
 
+    /// Reads IOIN and checks the silicon version.
+    ///
+    /// Returns the version (0x30 for the TMC2160), or `Error::WrongChip` if a different device,
+    /// or none at all, answers.
+    pub fn identify(&mut self) -> Result<u8, Error<SpiE, PinE>> {
+        let version = self.read_ioin()?.version() as u8;
+        if version != TMC2160_VERSION {
+            return Err(Error::WrongChip { version });
+        }
+        Ok(version)
+    }
+
+    /// Reads the IOIN register (input pin states and version).
+    pub fn read_ioin(&mut self) -> Result<IOIn, Error<SpiE, PinE>> {
+        Ok(IOIn(self.read_register(Register::IOIN)?))
+    }
+
     /// Reads a 32-bit register value via SPI.
     ///
-    /// This performs a 40-bit transfer: the first byte is the register address (read, MSB = 0)
-    /// followed by four dummy bytes. The returned data is parsed as a big-endian u32.
+    /// Each 40-bit transfer sends the register address (read, MSB = 0) followed by four dummy bytes.
+    /// The TMC2160 returns the data of a read request with the following datagram, so the request
+    /// is sent twice and the data of the second response is returned.
     pub fn read_register(&mut self, reg: Register) -> Result<u32, Error<SpiE, PinE>> {
         let addr = reg as u8; // For read, MSB remains 0.
-        let mut write_buf = [addr, 0, 0, 0, 0];
-        let mut read_buf = [0u8; 5];
-        self.cs.set_low().map_err(Error::Pin)?;
-        self.spi
-            .transfer(&mut write_buf, &mut read_buf)
-            .map_err(Error::Spi)?;
-        self.cs.set_high().map_err(Error::Pin)?;
-        let value = ((read_buf[1] as u32) << 24)
-            | ((read_buf[2] as u32) << 16)
-            | ((read_buf[3] as u32) << 8)
-            | (read_buf[4] as u32);
-        Ok(value)
+        self.transfer_datagram(addr, 0)?;
+        self.transfer_datagram(addr, 0)
     }
 
     /// Writes a 32-bit value to a register via SPI.
     ///
     /// The address is OR'd with 0x80 to indicate a write operation. The 32-bit data is sent MSB first.
+    ///
+    /// In write verification mode (see `set_write_verify`), registers with a `readback_mask` are
+    /// read back after writing and compared under the mask. On a mismatch the write is repeated
+    /// up to the configured number of retries before `Error::VerifyFailed` is returned.
     pub fn write_register(&mut self, reg: Register, value: u32) -> Result<(), Error<SpiE, PinE>> {
+        self.write_unverified(reg, value)?;
+        let (Some(retries), Some(mask)) = (self.write_verify, reg.readback_mask()) else {
+            return Ok(());
+        };
+        let mut attempt = 0;
+        loop {
+            let read = self.read_register(reg)?;
+            if read & mask == value & mask {
+                return Ok(());
+            }
+            if attempt == retries {
+                return Err(Error::VerifyFailed {
+                    reg,
+                    wrote: value,
+                    read,
+                });
+            }
+            attempt += 1;
+            self.write_unverified(reg, value)?;
+        }
+    }
+
+    /// Enables or disables write verification.
+    ///
+    /// With `Some(retries)`, every write to a register that can be read back is verified and
+    /// repeated at most `retries` times on a mismatch; `None` disables verification (the default).
+    /// Verification costs an additional read, i.e. two datagrams, per write.
+    pub fn set_write_verify(&mut self, retries: Option<u8>) {
+        self.write_verify = retries;
+    }
+
+    /// Returns the number of write retries if write verification is enabled.
+    pub fn write_verify(&self) -> Option<u8> {
+        self.write_verify
+    }
+
+    /// Writes a register without verifying it.
+    fn write_unverified(&mut self, reg: Register, value: u32) -> Result<(), Error<SpiE, PinE>> {
         let addr = (reg as u8) | 0x80;
-        let buf = [
+        self.transfer_datagram(addr, value)?;
+        self.update_register_cache(reg, value);
+        Ok(())
+    }
+
+    /// Performs a single 40-bit SPI datagram and returns the 32-bit data received.
+    ///
+    /// The data is sent and received MSB first, as a big-endian u32 following the address byte.
+    /// The status byte received in place of the address is kept for `spi_status`.
+    fn transfer_datagram(&mut self, addr: u8, value: u32) -> Result<u32, Error<SpiE, PinE>> {
+        let write_buf = [
             addr,
             (value >> 24) as u8,
             (value >> 16) as u8,
             (value >> 8) as u8,
             value as u8,
         ];
+        let mut read_buf = [0u8; 5];
         self.cs.set_low().map_err(Error::Pin)?;
-        self.spi.write(&buf).map_err(Error::Spi)?;
+        self.spi
+            .transfer(&mut read_buf, &write_buf)
+            .map_err(Error::Spi)?;
+        self.spi.flush().map_err(Error::Spi)?;
         self.cs.set_high().map_err(Error::Pin)?;
-        self.update_register_cache(reg, value);
+        self.spi_status = SpiStatus(read_buf[0]);
+        let value = ((read_buf[1] as u32) << 24)
+            | ((read_buf[2] as u32) << 16)
+            | ((read_buf[3] as u32) << 8)
+            | (read_buf[4] as u32);
+        Ok(value)
+    }
+
+    /// Returns the status byte received with the last SPI datagram.
+    pub fn spi_status(&self) -> SpiStatus {
+        self.spi_status
+    }
+
+    /// Checks the SPI link without writing any register.
+    ///
+    /// IOIN is read and its VERSION checked, and the SPI status byte must have its always-zero
+    /// bits clear. A bus with MISO stuck low fails the version check, one with MISO floating or
+    /// stuck high fails both. This is cheap enough to be run periodically, see `LinkMonitor`.
+    pub fn check_link(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        let version = self.read_ioin()?.version() as u8;
+        let status = self.spi_status;
+        if status.unused() != 0 {
+            return Err(Error::LinkFault(LinkFault::StatusStuck(status.0)));
+        }
+        if version != TMC2160_VERSION {
+            return Err(Error::LinkFault(LinkFault::Version(version)));
+        }
         Ok(())
     }
 
+    /// Tests the SPI link by writing test patterns and reading them back.
+    ///
+    /// In addition to `check_link`, two complementary patterns are written to a scratch register
+    /// and read back through the SPI pipeline, so every data line is seen at both levels. XDIRECT
+    /// serves as scratch register: TPWMTHRS and the other configuration registers of the TMC2160
+    /// are write only, and XDIRECT has no effect unless direct mode is enabled. Its previous value
+    /// is restored afterwards, also if the test fails. Returns `Error::LinkFault` on failure.
+    ///
+    /// In direct mode the patterns would drive coil currents, so `Error::DirectModeActive` is
+    /// returned without writing anything if GCONF.direct_mode is set.
+    pub fn link_self_test(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        self.check_link()?;
+        if GConf(self.read_register(Register::GConf)?).direct_mode() {
+            return Err(Error::DirectModeActive);
+        }
+        let saved = self.read_register(Register::XDirect)?;
+        let mut result = Ok(());
+        for pattern in LINK_TEST_PATTERNS {
+            result = self
+                .write_unverified(Register::XDirect, pattern)
+                .and_then(|()| match self.read_register(Register::XDirect)? {
+                    read if read == pattern => Ok(()),
+                    read => Err(Error::LinkFault(LinkFault::Readback {
+                        wrote: pattern,
+                        read,
+                    })),
+                });
+            if result.is_err() {
+                break;
+            }
+        }
+        let restored = self.write_register(Register::XDirect, saved);
+        result.and(restored)
+    }
+
     /// Performs a read-modify-write operation on a register.
     pub fn modify_register<F>(&mut self, reg: Register, f: F) -> Result<(), Error<SpiE, PinE>>
     where
@@ -134,6 +361,11 @@ where
             Register::TPwmThrs => self.register_cache.tpwmthrs = value,
             Register::CoolConf => self.register_cache.coolconf = value,
             Register::PwmConf => self.register_cache.pwmconf = value,
+            Register::GlobalScaler => self.register_cache.global_scaler = value,
+            Register::VdcMin => self.register_cache.vdcmin = value,
+            Register::DcCtrl => self.register_cache.dcctrl = value,
+            Register::ShortConf => self.register_cache.shortconf = value,
+            Register::DrvConf => self.register_cache.drvconf = value,
             _ => {} // Other registers are either readable or not cached.
         }
     }
@@ -150,24 +382,512 @@ where
 
     /// Sets the motor rotation direction.
     ///
-    /// Maps `Direction::CW` to one logic level and `Direction::CCW` to the other.
+    /// Maps `Direction::CW` to one logic level and `Direction::CCW` to the other. Steps in
+    /// `Direction::CW` increase the tracked position. When the direction changes, the DIR hold
+    /// time after the previous step and the DIR setup time before the next step are observed.
     pub fn set_direction(&mut self, direction: Direction) -> Result<(), Error<SpiE, PinE>> {
-        match direction {
-            Direction::CW => self.dir.set_low().map_err(Error::Pin),
-            Direction::CCW => self.dir.set_high().map_err(Error::Pin),
+        if direction == self.direction {
+            return self.set_dir_pin(direction);
         }
+        DelayNs::delay_ns(&mut self.delay, self.step_timing.dir_hold_ns);
+        self.set_dir_pin(direction)?;
+        DelayNs::delay_ns(&mut self.delay, self.step_timing.dir_setup_ns);
+        Ok(())
     }
 
     /// Generates a single step pulse by toggling the STEP pin.
     ///
-    /// If a minimum pulse width is required, insert a delay between setting the pin high and low.
+    /// STEP is held high and then low for the minimum times configured in `StepTiming`, so
+    /// consecutive calls never violate the pulse timing. In `SteppingMode::DoubleEdge` the pin is
+    /// toggled once instead, and held at its new level for the corresponding minimum time. The
+    /// tracked position is advanced by one step in the current direction.
+    ///
+    /// If the direction was reversed and backlash compensation is configured, the compensation
+    /// steps are issued first, spaced by `Backlash::step_interval_ns`.
+    ///
+    /// Returns `Error::EmergencyStop` while an emergency stop is active.
     pub fn step(&mut self) -> Result<(), Error<SpiE, PinE>> {
-        self.step.set_high().map_err(Error::Pin)?;
+        if self.emergency_stopped {
+            return Err(Error::EmergencyStop);
+        }
+        while self.take_backlash_step() {
+            self.pulse()?;
+            self.advance_backlash();
+            DelayNs::delay_ns(&mut self.delay, self.backlash.step_interval_ns);
+        }
+        self.pulse()?;
+        self.advance_position();
+        Ok(())
+    }
+
+    /// Issues one step on the STEP pin according to the stepping mode, observing the pulse timing.
+    fn pulse(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        self.last_step_edge = StepEdge::Unknown;
+        match self.stepping_mode {
+            SteppingMode::SingleEdge => {
+                self.set_step_pin(true)?;
+                DelayNs::delay_ns(&mut self.delay, self.step_timing.min_high_ns);
+                self.set_step_pin(false)?;
+                DelayNs::delay_ns(&mut self.delay, self.step_timing.min_low_ns);
+            }
+            SteppingMode::DoubleEdge => {
+                let level = self.toggle_step_pin()?;
+                let hold = if level {
+                    self.step_timing.min_high_ns
+                } else {
+                    self.step_timing.min_low_ns
+                };
+                DelayNs::delay_ns(&mut self.delay, hold);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the backlash compensation settings.
+    pub fn backlash(&self) -> Backlash {
+        self.backlash
+    }
+
+    /// Configures backlash compensation.
+    ///
+    /// Compensation applies to direction reversals after the next counted step; the first move
+    /// after construction is never compensated, since the mechanical state is unknown.
+    pub fn set_backlash(&mut self, backlash: Backlash) {
+        self.backlash = backlash;
+        self.pending_backlash = 0;
+    }
+
+    /// Consumes one pending compensation step. Returns `false` if none is pending.
+    pub(crate) fn take_backlash_step(&mut self) -> bool {
+        if self.pending_backlash == 0 {
+            return false;
+        }
+        self.pending_backlash -= 1;
+        true
+    }
+
+    /// Records an uncounted compensation step in the current direction.
+    pub(crate) fn advance_backlash(&mut self) {
+        let step_size = self.microsteps.step_size() as i64;
+        match self.direction {
+            Direction::CW => self.backlash_offset += step_size,
+            Direction::CCW => self.backlash_offset -= step_size,
+        }
+    }
+
+    /// Returns the STEP input mode.
+    pub fn stepping_mode(&self) -> SteppingMode {
+        self.stepping_mode
+    }
+
+    /// Selects single- or double-edge stepping by updating CHOPCONF.dedge.
+    ///
+    /// The STEP pin is only brought low while single-edge stepping is active, so switching modes
+    /// never produces a spurious step.
+    pub fn set_stepping_mode(&mut self, mode: SteppingMode) -> Result<(), Error<SpiE, PinE>> {
+        let mut chopconf = self.read_chopconf()?;
+        chopconf.set_dedge(mode == SteppingMode::DoubleEdge);
+        self.write_chopconf(chopconf)?;
+        self.stepping_mode = mode;
+        if mode == SteppingMode::SingleEdge && self.step_level {
+            // A falling edge does not step in single-edge mode.
+            self.set_step_pin(false)?;
+        }
+        Ok(())
+    }
 
-        // delay
-        DelayNs::delay_ns(&mut self.delay, 1000);
+    /// Returns the STEP/DIR interface timing.
+    pub fn step_timing(&self) -> StepTiming {
+        self.step_timing
+    }
 
-        self.step.set_low().map_err(Error::Pin)
+    /// Sets the STEP/DIR interface timing used by `step`, `set_direction` and the motion code.
+    pub fn set_step_timing(&mut self, timing: StepTiming) {
+        self.step_timing = timing;
+    }
+
+    /// Sets the DIR pin level without any delay. Used by the non-blocking `StepperTask`.
+    pub(crate) fn set_dir_pin(&mut self, direction: Direction) -> Result<(), Error<SpiE, PinE>> {
+        match direction {
+            Direction::CW => self.dir.set_low().map_err(Error::Pin)?,
+            Direction::CCW => self.dir.set_high().map_err(Error::Pin)?,
+        }
+        if direction != self.direction {
+            self.last_step_edge = StepEdge::None;
+        }
+        self.direction = direction;
+        // Schedule compensation when reversing relative to the last counted step. Returning to
+        // the previous direction before stepping cancels it again.
+        self.pending_backlash = match self.last_step_direction {
+            Some(last) if last != direction => self.backlash.microsteps,
+            _ => 0,
+        };
+        Ok(())
+    }
+
+    /// Records a STEP edge issued by the `StepperTask` at `now`.
+    pub(crate) fn record_step_edge(&mut self, now: u64) {
+        self.last_step_edge = StepEdge::At(now);
+    }
+
+    /// Returns the earliest time, not before `now`, at which DIR may change after the last step.
+    ///
+    /// The edge is kept across moves and tasks, so a new move starting in the opposite direction
+    /// still observes the DIR hold time. After a step of unknown time (issued by `step`), or an
+    /// edge recorded on a clock that is ahead of `now`, the full hold time from `now` is used.
+    pub(crate) fn dir_hold_until(&self, now: u64) -> u64 {
+        let hold = self.step_timing.dir_hold_ns as u64;
+        match self.last_step_edge {
+            StepEdge::None => now,
+            StepEdge::At(edge) => (edge + hold).clamp(now, now + hold),
+            StepEdge::Unknown => now + hold,
+        }
+    }
+
+    /// Sets the STEP pin level without any delay. Used by the non-blocking `StepperTask`.
+    pub(crate) fn set_step_pin(&mut self, high: bool) -> Result<(), Error<SpiE, PinE>> {
+        if high {
+            self.step.set_high().map_err(Error::Pin)?;
+        } else {
+            self.step.set_low().map_err(Error::Pin)?;
+        }
+        self.step_level = high;
+        Ok(())
+    }
+
+    /// Inverts the STEP pin level without any delay and returns the new level.
+    pub(crate) fn toggle_step_pin(&mut self) -> Result<bool, Error<SpiE, PinE>> {
+        let level = !self.step_level;
+        self.set_step_pin(level)?;
+        Ok(level)
+    }
+
+    /// Advances the tracked position by one step in the current direction.
+    pub(crate) fn advance_position(&mut self) {
+        let step_size = self.microsteps.step_size() as i64;
+        match self.direction {
+            Direction::CW => self.position += step_size,
+            Direction::CCW => self.position -= step_size,
+        }
+        self.last_step_direction = Some(self.direction);
+    }
+
+    /// Returns the current direction (the state of the DIR pin).
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the absolute position in steps of the current microstep resolution.
+    ///
+    /// The position is tracked in 1/256 microsteps internally, so changing the resolution with
+    /// `set_microsteps` rescales the value returned here. Positions that do not fall on a step of
+    /// the current resolution are rounded towards negative infinity.
+    pub fn position(&self) -> i64 {
+        self.position.div_euclid(self.microsteps.step_size() as i64)
+    }
+
+    /// Returns the absolute position in 1/256 microsteps, independent of the resolution.
+    pub fn position_fine(&self) -> i64 {
+        self.position
+    }
+
+    /// Redefines the current position, given in steps of the current microstep resolution.
+    ///
+    /// The motor does not move. The microstep counter (MSCNT) is read to establish the reference
+    /// used by `position_deviation`. This also clears the "position uncertain" state left by an
+    /// emergency stop.
+    pub fn set_position(&mut self, position: i64) -> Result<(), Error<SpiE, PinE>> {
+        let mscnt = self.read_mscnt()?;
+        self.position = position * self.microsteps.step_size() as i64;
+        self.backlash_offset = 0;
+        self.position_uncertain = false;
+        self.mscnt_offset = mscnt.wrapping_sub(self.position.rem_euclid(1024) as u16) % 1024;
+        Ok(())
+    }
+
+    /// Compares the tracked position against the microstep counter (MSCNT).
+    ///
+    /// MSCNT counts 1/256 microsteps modulo 1024 (one electrical cycle, i.e. four full steps), and
+    /// counts up for steps in `Direction::CW` unless GCONF.shaft is set. The returned value is the
+    /// signed difference `MSCNT - expected` in 1/256 microsteps, in the range -512..=511. A
+    /// non-zero result means that step pulses were missed (or extra pulses were received) since
+    /// the last call to `set_position`.
+    pub fn position_deviation(&mut self) -> Result<i16, Error<SpiE, PinE>> {
+        let mscnt = self.read_mscnt()?;
+        // Backlash compensation steps move the motor without changing the logical position.
+        let electrical = self.position + self.backlash_offset;
+        let expected = (self.mscnt_offset as i64 + electrical).rem_euclid(1024) as i16;
+        let deviation = (mscnt as i16 - expected).rem_euclid(1024);
+        Ok(if deviation >= 512 {
+            deviation - 1024
+        } else {
+            deviation
+        })
+    }
+
+    /// Enables or disables DCIN as hardware emergency stop input (GCONF.stop_enable).
+    ///
+    /// While enabled, a high level on DCIN stops the sequencer and holds the motor at standstill.
+    /// While disabled, DCIN is only the DcStep gating input and does not stop the motor.
+    pub fn set_stop_enable(&mut self, enabled: bool) -> Result<(), Error<SpiE, PinE>> {
+        self.modify_register(Register::GConf, |val| {
+            let mut gconf = GConf(val);
+            gconf.set_stop_enable(enabled);
+            gconf.0
+        })?;
+        self.stop_enabled = enabled;
+        Ok(())
+    }
+
+    /// Triggers a software emergency stop.
+    ///
+    /// The motion engine is frozen: `step()` returns `Error::EmergencyStop`, and `run` and
+    /// `StepperTask` stop with `MotionEvent::EmergencyStop` before the next step. A STEP pulse in
+    /// progress is terminated. The position is marked as uncertain.
+    ///
+    /// This only stops step generation by this driver. Use `emergency_stop_with` to also stop the
+    /// sequencer of the TMC2160 through DCIN.
+    pub fn emergency_stop(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        self.emergency_stopped = true;
+        self.position_uncertain = true;
+        if self.stepping_mode == SteppingMode::SingleEdge && self.step_level {
+            // A falling edge does not step in single-edge mode.
+            self.set_step_pin(false)?;
+        }
+        Ok(())
+    }
+
+    /// Freezes the motion engine like `emergency_stop`, then drives DCIN high to also stop the
+    /// sequencer of the TMC2160.
+    ///
+    /// The motion engine is frozen in any case. If stop_enable is not set (see
+    /// `set_stop_enable`), DCIN would not stop the sequencer; it is left untouched and
+    /// `Error::StopInputDisabled` is returned.
+    pub fn emergency_stop_with<P: OutputPin<Error = PinE>>(
+        &mut self,
+        input: &mut StopInput<P>,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        let stopped = self.emergency_stop();
+        if !self.stop_enabled {
+            stopped?;
+            return Err(Error::StopInputDisabled);
+        }
+        input.assert().map_err(Error::Pin)?;
+        stopped
+    }
+
+    /// Releases an emergency stop and unfreezes the motion engine.
+    ///
+    /// Returns `Error::EmergencyStop` if stop_enable is set and DCIN is still high, e.g. because an
+    /// external e-stop circuit is still asserted. The position stays uncertain until it is
+    /// redefined with `set_position` or by homing.
+    pub fn release(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        if self.stop_input_active()? {
+            return Err(Error::EmergencyStop);
+        }
+        self.emergency_stopped = false;
+        Ok(())
+    }
+
+    /// Drives DCIN low and releases the emergency stop like `release`.
+    pub fn release_with<P: OutputPin<Error = PinE>>(
+        &mut self,
+        input: &mut StopInput<P>,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        input.deassert().map_err(Error::Pin)?;
+        self.release()
+    }
+
+    /// Checks DCIN for an emergency stop asserted by external hardware.
+    ///
+    /// If stop_enable is set and DCIN is high, the motion engine is frozen like with
+    /// `emergency_stop`. Returns whether an emergency stop is active.
+    pub fn poll_stop_input(&mut self) -> Result<bool, Error<SpiE, PinE>> {
+        if self.stop_input_active()? && !self.emergency_stopped {
+            self.emergency_stop()?;
+        }
+        Ok(self.emergency_stopped)
+    }
+
+    /// Returns `true` while an emergency stop is active.
+    pub fn is_emergency_stopped(&self) -> bool {
+        self.emergency_stopped
+    }
+
+    /// Returns `true` if the position may be wrong after an emergency stop or direct mode.
+    ///
+    /// The position becomes certain again with `set_position` or by homing.
+    pub fn is_position_uncertain(&self) -> bool {
+        self.position_uncertain
+    }
+
+    /// Corrects the tracked position for `steps` input steps (of the current resolution) that
+    /// the motor did not follow.
+    pub(crate) fn discard_steps(&mut self, steps: i32) {
+        self.position -= steps as i64 * self.microsteps.step_size() as i64;
+    }
+
+    /// Marks the position as uncertain, e.g. when the coils are driven directly.
+    pub(crate) fn mark_position_uncertain(&mut self) {
+        self.position_uncertain = true;
+    }
+
+    /// Returns `true` if stop_enable is set and DCIN is high.
+    fn stop_input_active(&mut self) -> Result<bool, Error<SpiE, PinE>> {
+        if !self.stop_enabled {
+            return Ok(false);
+        }
+        Ok(self.read_ioin()?.enca_dcin_cfg5())
+    }
+
+    /// Reads the 10-bit microstep counter (MSCNT).
+    fn read_mscnt(&mut self) -> Result<u16, Error<SpiE, PinE>> {
+        let mscnt = MsCnt(self.read_register(Register::MsCnt)?);
+        Ok(mscnt.mscnt() as u16)
+    }
+
+    /// Returns the electrical angle from the microstep counter (MSCNT) in degrees (0.0..360.0).
+    ///
+    /// One electrical cycle (four full steps) corresponds to 1024 microstep counts. At 0°, coil A
+    /// is at START_SIN and coil B at its peak.
+    pub fn microstep_counter(&mut self) -> Result<f32, Error<SpiE, PinE>> {
+        let mscnt = self.read_mscnt()?;
+        Ok(mscnt as f32 * 360.0 / 1024.0)
+    }
+
+    /// Returns the actual coil A and coil B currents in mA (MSCURACT).
+    ///
+    /// MSCURACT holds the signed microstep table values. They are scaled by the actual current
+    /// scale (CS_ACTUAL in DRV_STATUS), GLOBAL_SCALER and the sense resistor, where the table value
+    /// 248 corresponds to the peak of the configured RMS current.
+    pub fn coil_currents(&mut self) -> Result<(i16, i16), Error<SpiE, PinE>> {
+        let cur = MsCurAct(self.read_register(Register::MsCurAct)?);
+        let cs_actual = DrvStatus(self.read_register(Register::DrvStatus)?).cs_actual();
+        let rms_ma = self.full_scale_current_ma() * (cs_actual as f32 + 1.0) / 32.0;
+        let peak_ma = rms_ma * core::f32::consts::SQRT_2;
+        let to_ma = |value: i16| libm::roundf(value as f32 * peak_ma / 248.0) as i16;
+        Ok((to_ma(cur.cur_a()), to_ma(cur.cur_b())))
+    }
+
+    /// Executes a motion profile (e.g. `Motion` or `SCurve`), blocking until it is complete.
+    ///
+    /// The profile is executed by a `StepperTask`, with the delay provider standing in for the
+    /// timer interrupt, so blocking and interrupt-driven stepping produce identical pin timing.
+    pub fn run<P: StepProfile>(
+        &mut self,
+        profile: &mut P,
+    ) -> Result<MotionEvent, Error<SpiE, PinE>> {
+        self.run_guarded(profile, ())
+    }
+
+    /// Executes a motion profile like `run`, checking `guard` (e.g. `LimitSwitches`) before every
+    /// step.
+    ///
+    /// Returns the event that ended motion. After `MotionEvent::LimitReached`, the profile has
+    /// already advanced by the refused step; re-synchronize it with `position()` before the next
+    /// move.
+    pub fn run_guarded<P: StepProfile, G: StepGuard<PinE>>(
+        &mut self,
+        profile: &mut P,
+        guard: G,
+    ) -> Result<MotionEvent, Error<SpiE, PinE>> {
+        let mut task = StepperTask::with_guard(profile, guard);
+        // Continue the clock from the last step edge of a previous move, so the DIR hold time is
+        // observed if this move starts in the opposite direction. The time between the moves is
+        // not known and counted as zero.
+        let mut now = match self.last_step_edge {
+            StepEdge::At(edge) => edge,
+            _ => 0,
+        };
+        while let Some(deadline) = task.tick(self, now)? {
+            let mut wait = deadline - now;
+            while wait > 0 {
+                let chunk = wait.min(u32::MAX as u64);
+                DelayNs::delay_ns(&mut self.delay, chunk as u32);
+                wait -= chunk;
+            }
+            now = deadline;
+        }
+        Ok(task.event().unwrap_or(MotionEvent::Completed))
+    }
+
+    /// Homes the axis against a limit switch.
+    ///
+    /// The axis moves towards `homing.limit` at `homing.velocity` until the switch triggers, then
+    /// reverses at `homing.backoff_velocity` until it releases. The position at the release point
+    /// is set to `homing.position`. Returns `Error::HomingFailed` if the switch is not fitted, is
+    /// not reached, or does not release within `homing.max_steps` steps.
+    pub fn home_to_switch<P: InputPin<Error = PinE>>(
+        &mut self,
+        switches: &mut LimitSwitches<P>,
+        homing: Homing,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        InvalidArgument::check(
+            None,
+            "velocity",
+            homing.velocity,
+            f32::MIN_POSITIVE,
+            f32::MAX,
+        )?;
+        InvalidArgument::check(
+            None,
+            "backoff_velocity",
+            homing.backoff_velocity,
+            f32::MIN_POSITIVE,
+            f32::MAX,
+        )?;
+        if !switches.has(homing.limit) {
+            return Err(Error::HomingFailed);
+        }
+        let toward = homing.limit.direction();
+        let away = match toward {
+            Direction::CW => Direction::CCW,
+            Direction::CCW => Direction::CW,
+        };
+        self.step_until(
+            switches,
+            homing.limit,
+            toward,
+            homing.velocity,
+            true,
+            homing.max_steps,
+        )?;
+        self.step_until(
+            switches,
+            homing.limit,
+            away,
+            homing.backoff_velocity,
+            false,
+            homing.max_steps,
+        )?;
+        self.set_position(homing.position)
+    }
+
+    /// Steps in `direction` at `velocity` steps per second until the switch for `limit` reaches
+    /// the `triggered` state, for at most `max_steps` steps.
+    fn step_until<P: InputPin<Error = PinE>>(
+        &mut self,
+        switches: &mut LimitSwitches<P>,
+        limit: Limit,
+        direction: Direction,
+        velocity: f32,
+        triggered: bool,
+        max_steps: u32,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        let interval_ns = (1.0e9 / velocity).min(u32::MAX as f32) as u32;
+        self.set_direction(direction)?;
+        for _ in 0..max_steps {
+            if switches.is_triggered(limit).map_err(Error::Pin)? == triggered {
+                return Ok(());
+            }
+            self.step()?;
+            DelayNs::delay_ns(&mut self.delay, interval_ns);
+        }
+        if switches.is_triggered(limit).map_err(Error::Pin)? == triggered {
+            Ok(())
+        } else {
+            Err(Error::HomingFailed)
+        }
     }
 
     /// Sets the motor current by configuring the IHOLD_IRUN register.
@@ -180,9 +900,10 @@ where
         hold_current: u8,
         hold_delay: u8,
     ) -> Result<(), Error<SpiE, PinE>> {
-        if run_current > 31 || hold_current > 31 || hold_delay > 7 {
-            return Err(Error::InvalidArgument);
-        }
+        let reg = Some(Register::IHoldIrun);
+        InvalidArgument::check(reg, "IRUN", run_current as f32, 0.0, 31.0)?;
+        InvalidArgument::check(reg, "IHOLD", hold_current as f32, 0.0, 31.0)?;
+        InvalidArgument::check(reg, "IHOLDDELAY", hold_delay as f32, 0.0, 7.0)?;
         let mut reg_val = IHoldIrun(0);
         reg_val.set_ihold(hold_current as u32);
         reg_val.set_irun(run_current as u32);
@@ -190,14 +911,230 @@ where
         self.write_register(Register::IHoldIrun, reg_val.0)
     }
 
+    /// Sets the sense resistor value in milliohms (75 mΩ by default).
+    ///
+    /// This is used to convert currents in mA into current scale values.
+    pub fn set_sense_resistor(&mut self, milliohms: u16) {
+        self.sense_resistor_mohm = milliohms;
+    }
+
+    /// Sets the GLOBAL_SCALER register, which scales all motor currents by `scaler / 256`.
+    ///
+    /// - `scaler` 0 selects full scale (256/256).
+    /// - `scaler` 1 to 31 is not allowed by the datasheet.
+    pub fn set_global_scaler(&mut self, scaler: u8) -> Result<(), Error<SpiE, PinE>> {
+        if scaler != 0 {
+            InvalidArgument::check(
+                Some(Register::GlobalScaler),
+                "GLOBAL_SCALER",
+                scaler as f32,
+                32.0,
+                255.0,
+            )?;
+        }
+        self.write_register(Register::GlobalScaler, scaler as u32)
+    }
+
+    /// Sets the run current (IRUN, 0..=31), keeping IHOLD and IHOLDDELAY from the cached
+    /// IHOLD_IRUN value.
+    pub fn set_run_current(&mut self, run_current: u8) -> Result<(), Error<SpiE, PinE>> {
+        InvalidArgument::check(
+            Some(Register::IHoldIrun),
+            "IRUN",
+            run_current as f32,
+            0.0,
+            31.0,
+        )?;
+        let mut ihold_irun = IHoldIrun(self.register_cache.ihold_irun);
+        ihold_irun.set_irun(run_current as u32);
+        self.write_register(Register::IHoldIrun, ihold_irun.0)
+    }
+
+    /// Returns the run current (IRUN) from the cached IHOLD_IRUN value.
+    pub fn run_current(&self) -> u8 {
+        IHoldIrun(self.register_cache.ihold_irun).irun() as u8
+    }
+
+    /// Selects the overtemperature shutdown threshold (DRV_CONF.OTSELECT).
+    ///
+    /// The other DRV_CONF fields are kept from the register cache.
+    pub fn set_ot_select(&mut self, ot_select: OtSelect) -> Result<(), Error<SpiE, PinE>> {
+        let mut drvconf = DrvConf(self.register_cache.drvconf);
+        drvconf.set_otselect(ot_select.to_bits() as u32);
+        self.write_register(Register::DrvConf, drvconf.0)
+    }
+
+    /// Returns the overtemperature shutdown threshold from the cached DRV_CONF value.
+    pub fn ot_select(&self) -> OtSelect {
+        OtSelect::from_bits(DrvConf(self.register_cache.drvconf).otselect() as u8)
+    }
+
+    /// Configures the short circuit detection (SHORT_CONF).
+    ///
+    /// S2VS_LEVEL must be within 4..=15 and S2G_LEVEL within 2..=15; lower values are more
+    /// sensitive. Use S2G_LEVEL ≥ 12 for supply voltages above 52V.
+    pub fn set_short_conf(&mut self, short_conf: ShortConf) -> Result<(), Error<SpiE, PinE>> {
+        let reg = Some(Register::ShortConf);
+        InvalidArgument::check(reg, "S2VS_LEVEL", short_conf.s2vs_level() as f32, 4.0, 15.0)?;
+        InvalidArgument::check(reg, "S2G_LEVEL", short_conf.s2g_level() as f32, 2.0, 15.0)?;
+        self.write_register(Register::ShortConf, short_conf.0)
+    }
+
+    /// Returns the cached SHORT_CONF value.
+    pub fn short_conf(&self) -> ShortConf {
+        ShortConf(self.register_cache.shortconf)
+    }
+
+    /// Sets the motor run and hold currents in mA RMS.
+    ///
+    /// The currents are converted into IRUN/IHOLD values using the sense resistor and the cached
+    /// GLOBAL_SCALER. The hold delay is kept from the cached IHOLD_IRUN value. Returns
+    /// `Error::InvalidArgument` if a current exceeds the range reachable with the current scaling.
+    pub fn set_current_ma(&mut self, run_ma: u16, hold_ma: u16) -> Result<(), Error<SpiE, PinE>> {
+        let run_current = self.current_scale(run_ma)?;
+        let hold_current = self.current_scale(hold_ma)?;
+        let hold_delay = IHoldIrun(self.register_cache.ihold_irun).iholddelay() as u8;
+        self.set_current(run_current, hold_current, hold_delay.min(7))
+    }
+
+    /// Returns the RMS current in mA corresponding to a current scale value (0..=31).
+    pub fn current_ma(&self, current_scale: u8) -> u16 {
+        let rms = self.full_scale_current_ma() * (current_scale as f32 + 1.0) / 32.0;
+        libm::roundf(rms) as u16
+    }
+
+    /// Converts an RMS current in mA into a current scale value (0..=31).
+    fn current_scale(&self, milliamps: u16) -> Result<u8, Error<SpiE, PinE>> {
+        let scale = libm::roundf(milliamps as f32 * 32.0 / self.full_scale_current_ma()) - 1.0;
+        if scale > 31.0 {
+            return Err(Error::InvalidArgument(InvalidArgument {
+                register: Some(Register::IHoldIrun),
+                field: "current_ma",
+                value: milliamps as f32,
+                min: 0.0,
+                max: self.current_ma(31) as f32,
+            }));
+        }
+        Ok(scale.max(0.0) as u8)
+    }
+
+    /// RMS current in mA at current scale 31, taking GLOBAL_SCALER into account.
+    fn full_scale_current_ma(&self) -> f32 {
+        let global_scaler = match self.register_cache.global_scaler & 0xFF {
+            0 => 256.0,
+            scaler => scaler as f32,
+        };
+        global_scaler / 256.0 * VFS_MV / self.sense_resistor_mohm as f32 * 1000.0
+            / core::f32::consts::SQRT_2
+    }
+
     /// Sets the microstepping resolution by updating the CHOPCONF register's MRES field.
+    ///
+    /// The tracked position is preserved; `position` reports it in steps of the new resolution.
     pub fn set_microsteps(
         &mut self,
         microsteps: MicrostepResolution,
     ) -> Result<(), Error<SpiE, PinE>> {
         let mut chopconf = self.read_chopconf()?;
         chopconf.set_mres(microsteps.to_bits() as u32);
-        self.write_chopconf(chopconf)
+        self.write_chopconf(chopconf)?;
+        self.microsteps = microsteps;
+        Ok(())
+    }
+
+    /// Returns the currently configured microstep resolution.
+    pub fn microsteps(&self) -> MicrostepResolution {
+        self.microsteps
+    }
+
+    /// Configures DcStep (VDCMIN and DCCTRL).
+    ///
+    /// DcStep requires the SpreadCycle chopper: StealthChop (GCONF.en_pwm_mode) must be off and
+    /// CHOPCONF.chm must select SpreadCycle. The chopper must be enabled (TOFF > 0, and TBL >= 2
+    /// for TOFF = 1), and DC_TIME must exceed the blank time selected by TBL. Returns
+    /// `Error::InvalidArgument` if the current chopper settings or the configuration violate these
+    /// rules, or if the minimum velocity does not fit into VDCMIN.
+    ///
+    /// DcStep itself is enabled by the DCEN input.
+    pub fn configure_dcstep(&mut self, config: DcStepConfig) -> Result<(), Error<SpiE, PinE>> {
+        InvalidArgument::check(None, "min_rpm", config.min_rpm, 0.0, f32::MAX)?;
+        InvalidArgument::check(
+            None,
+            "full_steps_per_rev",
+            config.full_steps_per_rev as f32,
+            1.0,
+            u16::MAX as f32,
+        )?;
+        InvalidArgument::check(None, "clock_hz", config.clock_hz as f32, 1.0, f32::MAX)?;
+        let gconf = GConf(self.read_register(Register::GConf)?);
+        let chopconf = self.read_chopconf()?;
+        let toff = chopconf.toff();
+        let tbl = chopconf.tbl();
+        let reg = Some(Register::GConf);
+        InvalidArgument::check(
+            reg,
+            "en_pwm_mode",
+            gconf.en_pwm_mode() as u8 as f32,
+            0.0,
+            0.0,
+        )?;
+        let reg = Some(Register::ChopConf);
+        InvalidArgument::check(reg, "chm", chopconf.chm() as u8 as f32, 0.0, 0.0)?;
+        InvalidArgument::check(reg, "TOFF", toff as f32, 1.0, 15.0)?;
+        if toff == 1 {
+            InvalidArgument::check(reg, "TBL", tbl as f32, 2.0, 3.0)?;
+        }
+        InvalidArgument::check(
+            Some(Register::DcCtrl),
+            "DC_TIME",
+            config.dc_time as f32,
+            (BLANK_TIME_CLOCKS[tbl as usize] + 1) as f32,
+            0x3FF as f32,
+        )?;
+        // VDCMIN is given in 1/256 microsteps per t = 2^24 / fCLK.
+        let usteps_per_s = config.min_rpm / 60.0 * config.full_steps_per_rev as f32 * 256.0;
+        let vdcmin = usteps_per_s * (1u32 << 24) as f32 / config.clock_hz as f32;
+        if vdcmin >= (1u32 << 23) as f32 {
+            return Err(Error::InvalidArgument(InvalidArgument {
+                register: Some(Register::VdcMin),
+                field: "VDCMIN",
+                value: vdcmin,
+                min: 0.0,
+                max: ((1u32 << 23) - 1) as f32,
+            }));
+        }
+        let mut vdcmin_reg = VdcMin(0);
+        vdcmin_reg.set_vdcmin(libm::roundf(vdcmin) as u32);
+        let mut dcctrl = DcCtrl(0);
+        dcctrl.set_dc_time(config.dc_time as u32);
+        dcctrl.set_dc_sg(config.dc_sg as u32);
+        self.write_register(Register::VdcMin, vdcmin_reg.0)?;
+        self.write_register(Register::DcCtrl, dcctrl.0)
+    }
+
+    /// Programs a microstep waveform (MSLUT[0..7], MSLUTSEL and MSLUTSTART).
+    ///
+    /// The table takes effect at the next zero crossing of the waveform; write it while the motor
+    /// is at standstill, e.g. during initialization.
+    pub fn write_microstep_table(
+        &mut self,
+        table: &MicrostepTable,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        const MSLUT: [Register; 8] = [
+            Register::MSLut0,
+            Register::MSLut1,
+            Register::MSLut2,
+            Register::MSLut3,
+            Register::MSLut4,
+            Register::MSLut5,
+            Register::MSLut6,
+            Register::MSLut7,
+        ];
+        for (reg, value) in MSLUT.into_iter().zip(table.lut()) {
+            self.write_register(reg, value)?;
+        }
+        self.write_register(Register::MSLutSel, table.sel().0)?;
+        self.write_register(Register::MSLutStart, table.start().0)
     }
 
     /// Reads the CHOPCONF register and returns a `ChopConf` bitfield.
@@ -213,33 +1150,192 @@ where
 
     /// Retrieves driver status by reading GSTAT and DRV_STATUS registers.
     ///
-    /// Returns a `DriverStatus` struct with decoded flags.
+    /// Returns a `DriverStatus` struct with decoded flags. GSTAT is not cleared; use
+    /// `clear_global_status` or `acknowledge_faults` to acknowledge its flags.
     pub fn get_driver_status(&mut self) -> Result<DriverStatus, Error<SpiE, PinE>> {
-        let gstat_val = self.read_register(Register::GStat)? as u8;
-        let drv_status_val = self.read_register(Register::DrvStatus)?;
+        let gstat = GStat(self.read_register(Register::GStat)?);
+        let drv_status = DrvStatus(self.read_register(Register::DrvStatus)?);
+        Ok(DriverStatus {
+            reset_flag: gstat.reset(),
+            drv_err: gstat.drv_err(),
+            uv_cp: gstat.uv_cp(),
+            short_to_gnd_a: drv_status.s2ga(),
+            short_to_gnd_b: drv_status.s2gb(),
+            open_load_a: drv_status.ola(),
+            open_load_b: drv_status.olb(),
+            stallguard_status: drv_status.stall_guard(),
+            stealth_mode: drv_status.stealth(),
+            cs_actual: drv_status.cs_actual() as u8,
+        })
+    }
 
-        let reset_flag = (gstat_val & 0x01) != 0;
-        let drv_err = (gstat_val & 0x02) != 0;
-        let uv_cp = (gstat_val & 0x04) != 0;
+    /// Reads GSTAT and clears the flags that were set.
+    ///
+    /// GSTAT flags are latched until a 1 is written to them. Returns the flags as read before
+    /// clearing. drv_err and uv_cp are set again immediately while their cause persists.
+    pub fn clear_global_status(&mut self) -> Result<GStat, Error<SpiE, PinE>> {
+        let gstat = GStat(self.read_register(Register::GStat)?);
+        self.write_register(Register::GStat, gstat.0)?;
+        Ok(gstat)
+    }
 
-        // Simplified decoding of DRV_STATUS.
-        let cs_actual = ((drv_status_val >> 16) & 0xFF) as u8;
-        let stealth_mode = (drv_status_val & (1 << 15)) != 0;
-        let stallguard_status = (drv_status_val & (1 << 14)) != 0;
+    /// Re-enables the driver after a short circuit or overtemperature shutdown and clears GSTAT.
+    ///
+    /// Short circuit shutdowns are latched until the driver is disabled, so the chopper is cycled
+    /// through TOFF = 0 before the previous TOFF value is restored. Returns the GSTAT flags that
+    /// remain set afterwards; drv_err stays set while the fault persists (e.g. the driver has not
+    /// cooled down below the overtemperature prewarning threshold).
+    pub fn acknowledge_faults(&mut self) -> Result<GStat, Error<SpiE, PinE>> {
+        let chopconf = self.read_chopconf()?;
+        let mut disabled = chopconf;
+        disabled.set_toff(0);
+        self.write_chopconf(disabled)?;
+        self.write_chopconf(chopconf)?;
+        self.clear_global_status()?;
+        Ok(GStat(self.read_register(Register::GStat)?))
+    }
 
-        // Additional status decoding can be added here.
-        Ok(DriverStatus {
-            reset_flag,
-            drv_err,
-            uv_cp,
-            short_to_gnd_a: false,
-            short_to_gnd_b: false,
-            open_load_a: false,
-            open_load_b: false,
-            stallguard_status,
-            stealth_mode,
-            cs_actual,
-        })
+    /// Reads GSTAT and DRV_STATUS and returns an error if the driver has shut down.
+    ///
+    /// Shorts (`Error::ShortToGround`, `Error::ShortToSupply`), overtemperature
+    /// (`Error::Overtemperature`) and charge pump undervoltage (`Error::Undervoltage`) are
+    /// reported in this order. Warnings such as the overtemperature prewarning are not errors;
+    /// see `FaultMonitor` and `ThermalPolicy` for those.
+    pub fn check_faults(&mut self) -> Result<(), Error<SpiE, PinE>> {
+        let gstat = GStat(self.read_register(Register::GStat)?);
+        let drv_status = DrvStatus(self.read_register(Register::DrvStatus)?);
+        if drv_status.s2ga() || drv_status.s2gb() {
+            return Err(Error::ShortToGround {
+                coil_a: drv_status.s2ga(),
+                coil_b: drv_status.s2gb(),
+            });
+        }
+        if drv_status.s2vsa() || drv_status.s2vsb() {
+            return Err(Error::ShortToSupply {
+                coil_a: drv_status.s2vsa(),
+                coil_b: drv_status.s2vsb(),
+            });
+        }
+        if drv_status.ot() {
+            return Err(Error::Overtemperature);
+        }
+        if gstat.uv_cp() {
+            return Err(Error::Undervoltage);
+        }
+        Ok(())
+    }
+
+    /// Checks the motor wiring for missing coils, cross-wired phases and shorts.
+    ///
+    /// Intended for commissioning, before motion is enabled; see the `wiring` module for the test
+    /// sequence. The driver is enabled during the test and left disabled afterwards, which also
+    /// releases a short circuit shutdown. GCONF and IHOLD_IRUN are restored, also if the test
+    /// fails, and the tracked position is marked as uncertain since the rotor follows the test
+    /// currents. On failure, the first error is returned.
+    pub fn wiring_self_test(
+        &mut self,
+        test: WiringTest,
+    ) -> Result<WiringReport, Error<SpiE, PinE>> {
+        InvalidArgument::check(None, "current", test.current as f32, 0.0, 31.0)?;
+        InvalidArgument::check(
+            None,
+            "reversals",
+            test.reversals as f32,
+            2.0,
+            u8::MAX as f32,
+        )?;
+        InvalidArgument::check(None, "steps", test.steps as f32, 1.0, u16::MAX as f32)?;
+        let gconf = self.read_register(Register::GConf)?;
+        let ihold_irun = self.register_cache.ihold_irun;
+        let mut spread_cycle = GConf(gconf);
+        spread_cycle.set_en_pwm_mode(false);
+        spread_cycle.set_direct_mode(false);
+        let report = self
+            .prepare_wiring_test(&test, spread_cycle)
+            .and_then(|()| self.run_wiring_test(&test, spread_cycle));
+        let disabled = self.disable_driver();
+        // The arguments of `and` are evaluated in any case, so every register is restored.
+        let restored = self
+            .write_register(Register::XDirect, 0)
+            .and(self.write_register(Register::GConf, gconf))
+            .and(self.write_register(Register::IHoldIrun, ihold_irun));
+        self.mark_position_uncertain();
+        let report = report?;
+        disabled.and(restored)?;
+        Ok(report)
+    }
+
+    /// Sets up SpreadCycle and the test current and enables the driver for `wiring_self_test`.
+    fn prepare_wiring_test(
+        &mut self,
+        test: &WiringTest,
+        gconf: GConf,
+    ) -> Result<(), Error<SpiE, PinE>> {
+        self.write_register(Register::GConf, gconf.0)?;
+        self.set_current(test.current, test.current, 0)?;
+        self.clear_global_status()?;
+        self.enable_driver()
+    }
+
+    /// Runs the phases of `wiring_self_test` with the driver enabled.
+    fn run_wiring_test(
+        &mut self,
+        test: &WiringTest,
+        gconf: GConf,
+    ) -> Result<WiringReport, Error<SpiE, PinE>> {
+        let mut direct = gconf;
+        direct.set_direct_mode(true);
+        self.write_register(Register::XDirect, 0)?;
+        self.write_register(Register::GConf, direct.0)?;
+        let mut open = [false; 2];
+        for (coil, open) in open.iter_mut().enumerate() {
+            let coil_a = coil == 0;
+            let status = self.energize_coil(coil_a, test)?;
+            if let Some(report) = WiringReport::from_shorts(status) {
+                return Ok(report);
+            }
+            *open = if coil_a { status.ola() } else { status.olb() };
+        }
+        self.write_register(Register::GConf, gconf.0)?;
+        for _ in 0..test.steps {
+            self.step()?;
+            DelayNs::delay_us(&mut self.delay, test.step_interval_us);
+        }
+        let status = DrvStatus(self.read_register(Register::DrvStatus)?);
+        if let Some(report) = WiringReport::from_shorts(status) {
+            return Ok(report);
+        }
+        Ok(WiringReport::from_open_load(
+            open[0],
+            open[1],
+            (status.ola(), status.olb()),
+        ))
+    }
+
+    /// Drives a single coil through XDIRECT with alternating polarity and returns DRV_STATUS.
+    fn energize_coil(
+        &mut self,
+        coil_a: bool,
+        test: &WiringTest,
+    ) -> Result<DrvStatus, Error<SpiE, PinE>> {
+        for reversal in 0..test.reversals {
+            let current = if reversal % 2 == 0 {
+                MAX_COIL_CURRENT
+            } else {
+                -MAX_COIL_CURRENT
+            };
+            let mut xdirect = XDirect(0);
+            if coil_a {
+                xdirect.set_coil_a(current);
+            } else {
+                xdirect.set_coil_b(current);
+            }
+            self.write_register(Register::XDirect, xdirect.0)?;
+            DelayNs::delay_us(&mut self.delay, test.dwell_us);
+        }
+        let status = DrvStatus(self.read_register(Register::DrvStatus)?);
+        self.write_register(Register::XDirect, 0)?;
+        Ok(status)
     }
 
     /// Resets the driver to a safe state by reconfiguring key registers.
@@ -251,3 +1347,637 @@ where
         Ok(())
     }
 }
+
+impl<SPI, CS, EN, DIR, STEP, D, SpiE, PinE> StepDir for Tmc2160<SPI, CS, EN, DIR, STEP, D>
+where
+    SPI: SpiBus<u8, Error = SpiE>,
+    CS: OutputPin<Error = PinE>,
+    EN: OutputPin<Error = PinE>,
+    DIR: OutputPin<Error = PinE>,
+    STEP: OutputPin<Error = PinE>,
+    D: DelayNs,
+{
+    type Error = Error<SpiE, PinE>;
+
+    fn set_direction(&mut self, direction: Direction) -> Result<(), Self::Error> {
+        Tmc2160::set_direction(self, direction)
+    }
+
+    fn step(&mut self) -> Result<(), Self::Error> {
+        Tmc2160::step(self)
+    }
+
+    fn position(&self) -> i64 {
+        Tmc2160::position(self)
+    }
+
+    fn pending_backlash(&self) -> u32 {
+        self.pending_backlash
+    }
+
+    fn backlash_interval_ns(&self) -> u32 {
+        self.backlash.step_interval_ns
+    }
+
+    fn backlash_step(&mut self) -> Result<(), Self::Error> {
+        if self.emergency_stopped {
+            return Err(Error::EmergencyStop);
+        }
+        if self.take_backlash_step() {
+            self.pulse()?;
+            self.advance_backlash();
+        }
+        Ok(())
+    }
+}
+
+impl<SPI, CS, EN, DIR, STEP, D, SpiE, PinE> GCodeAxis for Tmc2160<SPI, CS, EN, DIR, STEP, D>
+where
+    SPI: SpiBus<u8, Error = SpiE>,
+    CS: OutputPin<Error = PinE>,
+    EN: OutputPin<Error = PinE>,
+    DIR: OutputPin<Error = PinE>,
+    STEP: OutputPin<Error = PinE>,
+    D: DelayNs,
+{
+    fn enable(&mut self) -> Result<(), Self::Error> {
+        self.enable_driver()
+    }
+
+    fn disable(&mut self) -> Result<(), Self::Error> {
+        self.disable_driver()
+    }
+
+    /// Sets the run current; the hold current is set to half of it.
+    fn set_current_ma(&mut self, milliamps: u16) -> Result<(), Self::Error> {
+        Tmc2160::set_current_ma(self, milliamps, milliamps / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::limits::{ActiveLevel, LimitSwitch};
+    use crate::mock::{Bench, Input, Pin, PinFault, Steps};
+    use std::vec::Vec;
+
+    /// DCIN/CFG5 high in IOIN.
+    const DCIN_HIGH: u32 = 1 << 3;
+
+    fn mres(bench: &Bench) -> u32 {
+        ChopConf(bench.spi.reg(Register::ChopConf as u8)).mres()
+    }
+
+    #[test]
+    fn set_microsteps_rescales_position() {
+        let mut bench = Bench::new();
+        let driver = &mut bench.driver;
+        driver.set_microsteps(MicrostepResolution::Full).unwrap();
+        for _ in 0..3 {
+            driver.step().unwrap();
+        }
+        assert_eq!(driver.position(), 3);
+        assert_eq!(driver.position_fine(), 768);
+        assert_eq!(mres(&bench), 8);
+
+        let driver = &mut bench.driver;
+        driver
+            .set_microsteps(MicrostepResolution::TwoFiftySixth)
+            .unwrap();
+        assert_eq!(driver.position(), 768);
+        driver.set_direction(Direction::CCW).unwrap();
+        for _ in 0..8 {
+            driver.step().unwrap();
+        }
+        assert_eq!(driver.position(), 760);
+        assert_eq!(mres(&bench), 0);
+
+        let driver = &mut bench.driver;
+        driver
+            .set_microsteps(MicrostepResolution::Sixteenth)
+            .unwrap();
+        assert_eq!(driver.position(), 47);
+        assert_eq!(mres(&bench), 4);
+
+        // Off-grid positions round towards negative infinity, the fine position is kept.
+        let driver = &mut bench.driver;
+        driver.set_microsteps(MicrostepResolution::Full).unwrap();
+        assert_eq!(driver.position(), 2);
+        assert_eq!(driver.position_fine(), 760);
+        driver
+            .set_microsteps(MicrostepResolution::TwoFiftySixth)
+            .unwrap();
+        assert_eq!(driver.position(), 760);
+    }
+
+    #[test]
+    fn read_register_returns_the_requested_register() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::GStat as u8, 0x1);
+        bench.spi.set_reg(Register::MsCnt as u8, 0x2A);
+        assert_eq!(bench.driver.read_register(Register::GStat).unwrap(), 0x1);
+        assert_eq!(bench.driver.read_register(Register::MsCnt).unwrap(), 0x2A);
+    }
+
+    #[test]
+    fn position_deviation_reconciles_mscnt() {
+        let mut bench = Bench::new();
+        let mscnt = Register::MsCnt as u8;
+        bench.spi.set_reg(mscnt, 1000);
+        bench
+            .driver
+            .set_microsteps(MicrostepResolution::Full)
+            .unwrap();
+        bench.driver.set_position(0).unwrap();
+        assert_eq!(bench.driver.position_deviation().unwrap(), 0);
+
+        // Three full steps advance MSCNT by 768, wrapping at 1024.
+        for _ in 0..3 {
+            bench.driver.step().unwrap();
+        }
+        bench.spi.set_reg(mscnt, (1000 + 768) % 1024);
+        assert_eq!(bench.driver.position_deviation().unwrap(), 0);
+
+        // The device counted one 1/16 step more, or one full step less, than was sent.
+        bench.spi.set_reg(mscnt, (1000 + 768 + 16) % 1024);
+        assert_eq!(bench.driver.position_deviation().unwrap(), 16);
+        bench.spi.set_reg(mscnt, (1000 + 512) % 1024);
+        assert_eq!(bench.driver.position_deviation().unwrap(), -256);
+
+        // Redefining the position re-references MSCNT.
+        bench.driver.set_position(-7).unwrap();
+        assert_eq!(bench.driver.position(), -7);
+        assert_eq!(bench.driver.position_deviation().unwrap(), 0);
+    }
+
+    #[test]
+    fn stepping_mode_sets_dedge() {
+        let mut bench = Bench::new();
+        let dedge = |bench: &Bench| ChopConf(bench.spi.reg(Register::ChopConf as u8)).dedge();
+        bench
+            .driver
+            .set_stepping_mode(SteppingMode::DoubleEdge)
+            .unwrap();
+        assert!(dedge(&bench));
+        assert_eq!(bench.driver.stepping_mode(), SteppingMode::DoubleEdge);
+        bench
+            .driver
+            .set_stepping_mode(SteppingMode::SingleEdge)
+            .unwrap();
+        assert!(!dedge(&bench));
+    }
+
+    #[test]
+    fn double_edge_step_toggles_step() {
+        let mut bench = Bench::new();
+        bench
+            .driver
+            .set_stepping_mode(SteppingMode::DoubleEdge)
+            .unwrap();
+        bench.driver.step().unwrap();
+        // STEP stays high after the step; bringing it low would issue another one.
+        bench
+            .driver
+            .set_stepping_mode(SteppingMode::DoubleEdge)
+            .unwrap();
+        let levels = |bench: &Bench| -> Vec<bool> {
+            bench.step.edges().iter().map(|&(_, level)| level).collect()
+        };
+        assert_eq!(levels(&bench), [false, true]);
+        bench.driver.step().unwrap();
+        bench.driver.step().unwrap();
+        assert_eq!(levels(&bench), [false, true, false, true]);
+        assert_eq!(bench.driver.position(), 3);
+
+        // Single-edge stepping needs STEP low before the next rising edge; the falling edge does
+        // not step.
+        bench
+            .driver
+            .set_stepping_mode(SteppingMode::SingleEdge)
+            .unwrap();
+        assert_eq!(levels(&bench), [false, true, false, true, false]);
+        assert_eq!(bench.driver.position(), 3);
+    }
+
+    const BACKLASH: Backlash = Backlash {
+        microsteps: 3,
+        step_interval_ns: 10_000,
+    };
+
+    #[test]
+    fn backlash_steps_are_not_counted() {
+        let mut bench = Bench::new();
+        bench.driver.set_backlash(BACKLASH);
+        bench.driver.step().unwrap();
+        bench.driver.step().unwrap();
+        bench.driver.set_direction(Direction::CCW).unwrap();
+        assert_eq!(StepDir::pending_backlash(&bench.driver), 3);
+        let start = bench.clock.now();
+        bench.driver.step().unwrap();
+        assert_eq!(StepDir::pending_backlash(&bench.driver), 0);
+        assert_eq!(bench.driver.position(), 1);
+
+        // Three compensation steps, spaced by their interval, then the counted step.
+        let edges: Vec<u64> = bench
+            .step_edges(false)
+            .into_iter()
+            .filter(|&t| t >= start)
+            .collect();
+        assert_eq!(edges.len(), 4);
+        assert!(edges.windows(2).all(|pair| pair[1] - pair[0] >= 10_000));
+
+        // Further steps in the same direction are not compensated again.
+        bench.driver.step().unwrap();
+        assert_eq!(bench.step_edges(false).len(), 7);
+        assert_eq!(bench.driver.position(), 0);
+    }
+
+    #[test]
+    fn backlash_cancelled_by_restoring_direction() {
+        let mut bench = Bench::new();
+        bench.driver.set_backlash(BACKLASH);
+        bench.driver.step().unwrap();
+        bench.driver.set_direction(Direction::CCW).unwrap();
+        bench.driver.set_direction(Direction::CW).unwrap();
+        assert_eq!(StepDir::pending_backlash(&bench.driver), 0);
+        bench.driver.step().unwrap();
+        assert_eq!(bench.step_edges(false).len(), 2);
+        assert_eq!(bench.driver.position(), 2);
+    }
+
+    #[test]
+    fn stepper_task_compensates_backlash() {
+        let mut bench = Bench::new();
+        bench.driver.set_backlash(BACKLASH);
+        bench.run_task(&mut StepperTask::new(Steps::new(2, 1_000, Direction::CW)));
+        bench.run_task(&mut StepperTask::new(Steps::new(2, 1_000, Direction::CCW)));
+        assert_eq!(bench.step_edges(false).len(), 7);
+        assert_eq!(bench.driver.position(), 0);
+    }
+
+    const HOMING: Homing = Homing {
+        limit: Limit::Min,
+        velocity: 1_000.0,
+        backoff_velocity: 100.0,
+        max_steps: 10,
+        position: 5,
+    };
+
+    #[test]
+    fn homes_with_back_off() {
+        let mut bench = Bench::new();
+        // Active-low switch: three steps to reach it, two to back off until it releases.
+        let pin = Input::new(&[true, true, true, false, false, false, true]);
+        let min = LimitSwitch::new(pin, ActiveLevel::Low);
+        let mut switches = LimitSwitches::new(Some(min), None);
+        bench.driver.home_to_switch(&mut switches, HOMING).unwrap();
+        assert_eq!(bench.driver.position(), 5);
+        assert_eq!(bench.driver.direction(), Direction::CW);
+        let edges = bench.step_edges(false);
+        assert_eq!(edges.len(), 5);
+        // The back-off steps run at the back-off velocity.
+        assert!(edges[4] - edges[3] >= 10_000_000);
+        assert!(edges[2] - edges[1] < 10_000_000);
+        assert_eq!(bench.dir_changes().len(), 2);
+    }
+
+    #[test]
+    fn homing_fails_without_switch() {
+        let mut bench = Bench::new();
+        let max = LimitSwitch::new(Input::new(&[false]), ActiveLevel::High);
+        let mut switches = LimitSwitches::new(None, Some(max));
+        let err = bench.driver.home_to_switch(&mut switches, HOMING);
+        assert!(matches!(err, Err(Error::HomingFailed)));
+        assert!(bench.step_edges(false).is_empty());
+
+        // The switch never triggers within max_steps.
+        let homing = Homing {
+            limit: Limit::Max,
+            ..HOMING
+        };
+        let err = bench.driver.home_to_switch(&mut switches, homing);
+        assert!(matches!(err, Err(Error::HomingFailed)));
+        assert_eq!(bench.step_edges(false).len(), 10);
+    }
+
+    #[test]
+    fn run_guarded_stops_at_limit() {
+        let mut bench = Bench::new();
+        let max = LimitSwitch::new(Input::new(&[false, false, true]), ActiveLevel::High);
+        let mut switches = LimitSwitches::new(None, Some(max));
+        let event = bench
+            .driver
+            .run_guarded(&mut Steps::new(5, 1_000, Direction::CW), &mut switches)
+            .unwrap();
+        assert_eq!(event, MotionEvent::LimitReached(Limit::Max));
+        assert_eq!(bench.driver.position(), 2);
+    }
+
+    #[test]
+    fn emergency_stop_freezes_motion() {
+        let mut bench = Bench::new();
+        let pin = Pin::new(&bench.clock);
+        let mut dcin = StopInput::new(pin.clone()).unwrap();
+        bench.driver.set_stop_enable(true).unwrap();
+        bench.driver.step().unwrap();
+        assert!(!bench.driver.is_position_uncertain());
+
+        bench.driver.emergency_stop_with(&mut dcin).unwrap();
+        assert_eq!(pin.edges().last(), Some(&(bench.clock.now(), true)));
+        assert!(bench.driver.is_emergency_stopped());
+        assert!(bench.driver.is_position_uncertain());
+        assert!(matches!(bench.driver.step(), Err(Error::EmergencyStop)));
+        let event = bench
+            .driver
+            .run(&mut Steps::new(5, 1_000, Direction::CW))
+            .unwrap();
+        assert_eq!(event, MotionEvent::EmergencyStop);
+        assert_eq!(bench.step_edges(false).len(), 1);
+        assert_eq!(bench.driver.position(), 1);
+    }
+
+    #[test]
+    fn release_fails_while_dcin_high() {
+        let mut bench = Bench::new();
+        bench.driver.set_stop_enable(true).unwrap();
+        bench
+            .spi
+            .set_reg(Register::IOIN as u8, 0x3000_0000 | DCIN_HIGH);
+        assert!(bench.driver.poll_stop_input().unwrap());
+        assert!(matches!(bench.driver.release(), Err(Error::EmergencyStop)));
+        assert!(bench.driver.is_emergency_stopped());
+
+        bench.spi.set_reg(Register::IOIN as u8, 0x3000_0000);
+        bench.driver.release().unwrap();
+        bench.driver.step().unwrap();
+        assert!(bench.driver.is_position_uncertain());
+        bench.driver.set_position(0).unwrap();
+        assert!(!bench.driver.is_position_uncertain());
+    }
+
+    #[test]
+    fn emergency_stop_with_freezes_without_stop_enable() {
+        let mut bench = Bench::new();
+        let pin = Pin::new(&bench.clock);
+        let mut dcin = StopInput::new(pin.clone()).unwrap();
+        let err = bench.driver.emergency_stop_with(&mut dcin);
+        assert!(matches!(err, Err(Error::StopInputDisabled)));
+        assert!(bench.driver.is_emergency_stopped());
+        assert!(matches!(bench.driver.step(), Err(Error::EmergencyStop)));
+        assert_eq!(pin.edges(), [(0, false)]);
+    }
+
+    /// Sets CHOPCONF to SpreadCycle with TOFF = 3 and TBL = 1 (24 clocks blank time).
+    fn spreadcycle(bench: &Bench) {
+        let mut chopconf = ChopConf(0);
+        chopconf.set_toff(3);
+        chopconf.set_tbl(1);
+        bench.spi.set_reg(Register::ChopConf as u8, chopconf.0);
+    }
+
+    #[test]
+    fn configure_dcstep_packs_registers() {
+        let mut bench = Bench::new();
+        spreadcycle(&bench);
+        assert_eq!(bench.spi.reg(Register::ChopConf as u8), 3 | 1 << 15);
+        bench
+            .driver
+            .configure_dcstep(DcStepConfig::default())
+            .unwrap();
+        // 30 rpm * 200 full steps * 256 / 60 s * 2^24 / 12 MHz = 35791.4
+        assert_eq!(bench.spi.reg(Register::VdcMin as u8), 35_791);
+        assert_eq!(bench.spi.reg(Register::DcCtrl as u8), 40 | 3 << 16);
+    }
+
+    #[test]
+    fn configure_dcstep_rejects_invalid_chopper() {
+        let mut bench = Bench::new();
+        spreadcycle(&bench);
+        let short = DcStepConfig {
+            dc_time: 24,
+            ..DcStepConfig::default()
+        };
+        let err = bench.driver.configure_dcstep(short);
+        assert!(matches!(err, Err(Error::InvalidArgument(_))));
+        let fast = DcStepConfig {
+            min_rpm: 10_000.0,
+            ..DcStepConfig::default()
+        };
+        let err = bench.driver.configure_dcstep(fast);
+        assert!(matches!(err, Err(Error::InvalidArgument(_))));
+
+        let mut gconf = GConf(0);
+        gconf.set_en_pwm_mode(true);
+        bench.spi.set_reg(Register::GConf as u8, gconf.0);
+        let err = bench.driver.configure_dcstep(DcStepConfig::default());
+        assert!(matches!(err, Err(Error::InvalidArgument(_))));
+        assert_eq!(bench.spi.reg(Register::DcCtrl as u8), 0);
+    }
+
+    #[test]
+    fn mscuract_sign_extends_coil_currents() {
+        let cur = MsCurAct(0x1FF | 0x100 << 16);
+        assert_eq!((cur.cur_a(), cur.cur_b()), (-1, -256));
+        let cur = MsCurAct(0x0F8 | 0x108 << 16);
+        assert_eq!((cur.cur_a(), cur.cur_b()), (248, -248));
+    }
+
+    #[test]
+    fn coil_currents_scale_table_peak() {
+        let mut bench = Bench::new();
+        // 100 mΩ at CS_ACTUAL = 31: 325 mV / 100 mΩ = 3250 mA peak, reached at the table value 248.
+        bench.driver.set_sense_resistor(100);
+        bench.spi.set_reg(Register::DrvStatus as u8, 31 << 16);
+        bench
+            .spi
+            .set_reg(Register::MsCurAct as u8, (512 - 124) | 248 << 16);
+        assert_eq!(bench.driver.coil_currents().unwrap(), (-1625, 3250));
+
+        // CS_ACTUAL = 15 halves the current, GLOBAL_SCALER = 128 halves it again.
+        bench.spi.set_reg(Register::DrvStatus as u8, 15 << 16);
+        bench.driver.set_global_scaler(128).unwrap();
+        assert_eq!(bench.driver.coil_currents().unwrap(), (-406, 813));
+
+        bench.spi.set_reg(Register::MsCnt as u8, 256);
+        assert_eq!(bench.driver.microstep_counter().unwrap(), 90.0);
+    }
+
+    #[test]
+    fn clear_global_status_writes_set_flags() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::GStat as u8, 0b101);
+        let gstat = bench.driver.clear_global_status().unwrap();
+        assert!(gstat.reset() && !gstat.drv_err() && gstat.uv_cp());
+        assert_eq!(bench.spi.writes_to(Register::GStat as u8), [0b101]);
+        assert_eq!(bench.spi.reg(Register::GStat as u8), 0);
+    }
+
+    #[test]
+    fn acknowledge_faults_cycles_toff() {
+        let mut bench = Bench::new();
+        let mut chopconf = ChopConf(bench.spi.reg(Register::ChopConf as u8));
+        chopconf.set_toff(5);
+        chopconf.set_tbl(2);
+        bench.driver.write_chopconf(chopconf).unwrap();
+        bench.spi.set_reg(Register::GStat as u8, 0b010);
+        bench.spi.writes.borrow_mut().clear();
+
+        let gstat = bench.driver.acknowledge_faults().unwrap();
+        assert_eq!(gstat, GStat(0));
+        let mut disabled = chopconf;
+        disabled.set_toff(0);
+        assert_eq!(
+            bench.spi.writes_to(Register::ChopConf as u8),
+            [disabled.0, chopconf.0]
+        );
+        assert_eq!(bench.spi.writes_to(Register::GStat as u8), [0b010]);
+    }
+
+    #[test]
+    fn identifies_tmc2160_version() {
+        let mut bench = Bench::new();
+        assert_eq!(bench.driver.identify().unwrap(), 0x30);
+        bench.driver.init().unwrap();
+
+        for version in [0x00, 0x11, 0x31, 0xFF] {
+            bench.spi.set_reg(Register::IOIN as u8, version << 24);
+            let err = bench.driver.identify();
+            assert!(
+                matches!(err, Err(Error::WrongChip { version: v }) if v as u32 == version),
+                "{version:#x}"
+            );
+            assert!(matches!(bench.driver.init(), Err(Error::WrongChip { .. })));
+        }
+    }
+
+    #[test]
+    fn link_self_test_restores_xdirect() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::XDirect as u8, 0x0010_0120);
+        bench.driver.link_self_test().unwrap();
+        assert_eq!(bench.spi.reg(Register::XDirect as u8), 0x0010_0120);
+
+        // A data line stuck high fails the readback of the pattern with that bit clear.
+        bench.spi.stuck.set(0x0000_0100);
+        let err = bench.driver.link_self_test();
+        assert!(matches!(
+            err,
+            Err(Error::LinkFault(LinkFault::Readback {
+                wrote: 0x0155_00AA,
+                read: 0x0155_01AA,
+            }))
+        ));
+        assert_eq!(bench.spi.reg(Register::XDirect as u8), 0x0010_0120);
+    }
+
+    #[test]
+    fn link_self_test_refuses_direct_mode() {
+        let mut bench = Bench::new();
+        bench.spi.set_reg(Register::GConf as u8, 1 << 16);
+        bench.spi.set_reg(Register::XDirect as u8, 0x0010_0020);
+        let err = bench.driver.link_self_test();
+        assert!(matches!(err, Err(Error::DirectModeActive)));
+        assert_eq!(bench.spi.reg(Register::XDirect as u8), 0x0010_0020);
+    }
+
+    #[test]
+    fn write_verify_retries_then_fails() {
+        let mut bench = Bench::new();
+        bench.driver.set_write_verify(Some(2));
+        // A stuck bit within the readback mask fails every attempt.
+        bench.spi.stuck.set(1 << 31);
+        bench.spi.writes.borrow_mut().clear();
+        let err = bench.driver.write_register(Register::ChopConf, 0x0000_0005);
+        assert!(matches!(
+            err,
+            Err(Error::VerifyFailed {
+                reg: Register::ChopConf,
+                wrote: 0x0000_0005,
+                read: 0x8000_0005,
+            })
+        ));
+        assert_eq!(bench.spi.writes_to(Register::ChopConf as u8).len(), 3);
+    }
+
+    #[test]
+    fn write_verify_ignores_bits_outside_mask() {
+        let mut bench = Bench::new();
+        bench.driver.set_write_verify(Some(2));
+        // CHOPCONF bit 13 is reserved and excluded from the mask.
+        bench.spi.stuck.set(1 << 13);
+        bench.spi.writes.borrow_mut().clear();
+        bench
+            .driver
+            .write_register(Register::ChopConf, 0x0000_0005)
+            .unwrap();
+        // Write-only registers are not read back.
+        bench
+            .driver
+            .write_register(Register::GlobalScaler, 0x80)
+            .unwrap();
+        assert_eq!(bench.spi.writes_to(Register::ChopConf as u8), [5]);
+        assert_eq!(bench.spi.writes_to(Register::GlobalScaler as u8), [0x80]);
+
+        bench.driver.set_write_verify(None);
+        bench.spi.stuck.set(1 << 31);
+        bench
+            .driver
+            .write_register(Register::ChopConf, 0x0000_0005)
+            .unwrap();
+    }
+
+    #[test]
+    fn wiring_self_test_restores_configuration() {
+        let mut bench = Bench::new();
+        let mut gconf = GConf(0);
+        gconf.set_en_pwm_mode(true);
+        gconf.set_shaft(true);
+        bench
+            .driver
+            .write_register(Register::GConf, gconf.0)
+            .unwrap();
+        bench.driver.set_current(20, 10, 6).unwrap();
+        let ihold_irun = bench.spi.reg(Register::IHoldIrun as u8);
+
+        let report = bench.driver.wiring_self_test(WiringTest::default());
+        assert_eq!(report.unwrap(), WiringReport::Ok);
+        assert_eq!(bench.spi.reg(Register::GConf as u8), gconf.0);
+        assert_eq!(bench.spi.reg(Register::IHoldIrun as u8), ihold_irun);
+        assert_eq!(bench.spi.reg(Register::XDirect as u8), 0);
+        // The driver is left disabled (EN high).
+        assert_eq!(bench.en.edges().last().map(|&(_, level)| level), Some(true));
+        assert!(bench.driver.is_position_uncertain());
+
+        bench.spi.set_reg(Register::DrvStatus as u8, 1 << 28);
+        let report = bench.driver.wiring_self_test(WiringTest::default());
+        assert_eq!(
+            report.unwrap(),
+            WiringReport::ShortToGround {
+                coil_a: false,
+                coil_b: true,
+            }
+        );
+    }
+
+    #[test]
+    fn wiring_self_test_restores_configuration_on_error() {
+        let mut bench = Bench::new();
+        let mut gconf = GConf(0);
+        gconf.set_en_pwm_mode(true);
+        bench
+            .driver
+            .write_register(Register::GConf, gconf.0)
+            .unwrap();
+        bench.driver.set_current(20, 10, 6).unwrap();
+        let ihold_irun = bench.spi.reg(Register::IHoldIrun as u8);
+
+        // Both enabling and disabling the driver fail.
+        bench.en.fail.set(true);
+        let report = bench.driver.wiring_self_test(WiringTest::default());
+        assert!(matches!(report, Err(Error::Pin(PinFault))));
+        assert_eq!(bench.spi.reg(Register::GConf as u8), gconf.0);
+        assert_eq!(bench.spi.reg(Register::IHoldIrun as u8), ihold_irun);
+    }
+}
@@ -1,17 +1,263 @@
 //! Common types for the TMC2160 driver crate.
 
+use crate::registers::Register;
+use core::fmt;
+use embedded_hal::{digital, spi};
+
 /// Generic error type returned by TMC2160 driver functions.
 /// `SpiE` is the error type for SPI operations and `PinE` is the error type for GPIO operations.
-#[derive(Debug)]
+///
+/// Application error types can wrap it directly, or wrap `ErrorKind` (see `into_kind`) to avoid
+/// the bus error type parameters. With `Display` and `core::error::Error` implemented, it also
+/// converts into boxed or trait-object errors with `?`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error<SpiE, PinE> {
     /// An error occurred during an SPI transaction.
     Spi(SpiE),
     /// An error occurred while toggling or reading a GPIO pin.
     Pin(PinE),
-    /// An argument was provided that is outside the accepted range.
-    InvalidArgument,
+    /// An argument or register field is outside the accepted range.
+    InvalidArgument(InvalidArgument),
     /// The driver has not been properly initialized.
     NotInitialized,
+    /// Homing did not find the switch, or the switch did not release, within the step limit.
+    HomingFailed,
+    /// An emergency stop is active, or its input is still asserted.
+    EmergencyStop,
+    /// DCIN is not enabled as emergency stop input (GCONF.stop_enable).
+    StopInputDisabled,
+    /// The operation is not possible while direct mode (GCONF.direct_mode) is enabled.
+    DirectModeActive,
+    /// The device did not identify as a TMC2160 (IOIN.VERSION). A floating or disconnected SPI
+    /// bus typically reads as 0x00 or 0xFF.
+    WrongChip {
+        /// VERSION value that was read.
+        version: u8,
+    },
+    /// The SPI link failed its integrity check.
+    LinkFault(LinkFault),
+    /// More steps were lost than the step loss monitor tolerates.
+    StepLoss {
+        /// Number of lost steps since the monitor was reset.
+        count: u32,
+    },
+    /// A register did not read back as written in write verification mode.
+    VerifyFailed {
+        /// The register that was written.
+        reg: Register,
+        /// The value written.
+        wrote: u32,
+        /// The value read back on the last attempt.
+        read: u32,
+    },
+    /// The driver has shut down due to overtemperature (DRV_STATUS.ot).
+    Overtemperature,
+    /// The driver has shut down due to a short to ground (DRV_STATUS.s2ga/s2gb).
+    ShortToGround {
+        /// Short on coil A.
+        coil_a: bool,
+        /// Short on coil B.
+        coil_b: bool,
+    },
+    /// The driver has shut down due to a short to supply (DRV_STATUS.s2vsa/s2vsb).
+    ShortToSupply {
+        /// Short on coil A.
+        coil_a: bool,
+        /// Short on coil B.
+        coil_b: bool,
+    },
+    /// The driver is disabled due to charge pump undervoltage (GSTAT.uv_cp).
+    Undervoltage,
+}
+
+/// `Error` with the SPI and pin errors replaced by their embedded-hal error kinds.
+pub type ErrorKind = Error<spi::ErrorKind, digital::ErrorKind>;
+
+impl<SpiE, PinE> Error<SpiE, PinE> {
+    /// Converts the SPI and pin errors with the given functions.
+    pub fn map_bus<S, P>(
+        self,
+        spi: impl FnOnce(SpiE) -> S,
+        pin: impl FnOnce(PinE) -> P,
+    ) -> Error<S, P> {
+        match self {
+            Error::Spi(err) => Error::Spi(spi(err)),
+            Error::Pin(err) => Error::Pin(pin(err)),
+            Error::InvalidArgument(arg) => Error::InvalidArgument(arg),
+            Error::NotInitialized => Error::NotInitialized,
+            Error::HomingFailed => Error::HomingFailed,
+            Error::EmergencyStop => Error::EmergencyStop,
+            Error::StopInputDisabled => Error::StopInputDisabled,
+            Error::DirectModeActive => Error::DirectModeActive,
+            Error::WrongChip { version } => Error::WrongChip { version },
+            Error::LinkFault(fault) => Error::LinkFault(fault),
+            Error::StepLoss { count } => Error::StepLoss { count },
+            Error::VerifyFailed { reg, wrote, read } => Error::VerifyFailed { reg, wrote, read },
+            Error::Overtemperature => Error::Overtemperature,
+            Error::ShortToGround { coil_a, coil_b } => Error::ShortToGround { coil_a, coil_b },
+            Error::ShortToSupply { coil_a, coil_b } => Error::ShortToSupply { coil_a, coil_b },
+            Error::Undervoltage => Error::Undervoltage,
+        }
+    }
+
+    /// Returns `true` for errors reported by the device itself (link faults, a wrong chip,
+    /// failed write verification and driver shutdowns), as opposed to bus, argument or motion
+    /// errors.
+    pub fn is_device_fault(&self) -> bool {
+        matches!(
+            self,
+            Error::WrongChip { .. }
+                | Error::LinkFault(_)
+                | Error::VerifyFailed { .. }
+                | Error::Overtemperature
+                | Error::ShortToGround { .. }
+                | Error::ShortToSupply { .. }
+                | Error::Undervoltage
+        )
+    }
+}
+
+impl<SpiE: spi::Error, PinE: digital::Error> Error<SpiE, PinE> {
+    /// Replaces the SPI and pin errors with their embedded-hal error kinds.
+    pub fn into_kind(self) -> ErrorKind {
+        self.map_bus(|err| err.kind(), |err| err.kind())
+    }
+}
+
+impl<SpiE, PinE> From<InvalidArgument> for Error<SpiE, PinE> {
+    fn from(arg: InvalidArgument) -> Self {
+        Error::InvalidArgument(arg)
+    }
+}
+
+impl<SpiE: fmt::Debug, PinE: fmt::Debug> fmt::Display for Error<SpiE, PinE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Spi(err) => write!(f, "SPI error: {err:?}"),
+            Error::Pin(err) => write!(f, "pin error: {err:?}"),
+            Error::InvalidArgument(arg) => write!(f, "invalid argument: {arg}"),
+            Error::NotInitialized => f.write_str("driver not initialized"),
+            Error::HomingFailed => f.write_str("homing failed"),
+            Error::EmergencyStop => f.write_str("emergency stop active"),
+            Error::StopInputDisabled => f.write_str("emergency stop input not enabled"),
+            Error::DirectModeActive => f.write_str("direct mode active"),
+            Error::WrongChip { version } => {
+                write!(f, "wrong chip: version {version:#04x}, expected 0x30")
+            }
+            Error::LinkFault(fault) => write!(f, "SPI link fault: {fault}"),
+            Error::StepLoss { count } => write!(f, "{count} steps lost"),
+            Error::VerifyFailed { reg, wrote, read } => write!(
+                f,
+                "write verification of {reg:?} failed: wrote {wrote:#010x}, read {read:#010x}"
+            ),
+            Error::Overtemperature => f.write_str("overtemperature shutdown"),
+            Error::ShortToGround { coil_a, coil_b } => {
+                write!(f, "short to ground on {}", Coils(*coil_a, *coil_b))
+            }
+            Error::ShortToSupply { coil_a, coil_b } => {
+                write!(f, "short to supply on {}", Coils(*coil_a, *coil_b))
+            }
+            Error::Undervoltage => f.write_str("charge pump undervoltage"),
+        }
+    }
+}
+
+impl<SpiE: fmt::Debug, PinE: fmt::Debug> core::error::Error for Error<SpiE, PinE> {}
+
+/// Formats the coils flagged in a short circuit error.
+struct Coils(bool, bool);
+
+impl fmt::Display for Coils {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.0, self.1) {
+            (true, true) => f.write_str("coils A and B"),
+            (true, false) => f.write_str("coil A"),
+            (false, true) => f.write_str("coil B"),
+            (false, false) => f.write_str("no coil"),
+        }
+    }
+}
+
+/// Description of an argument that failed validation.
+///
+/// Values and bounds are given as `f32`, which represents all register field values exactly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidArgument {
+    /// Register the argument is written to, if it maps to one.
+    pub register: Option<Register>,
+    /// Name of the argument or register field.
+    pub field: &'static str,
+    /// The rejected value.
+    pub value: f32,
+    /// Smallest accepted value.
+    pub min: f32,
+    /// Largest accepted value.
+    pub max: f32,
+}
+
+impl InvalidArgument {
+    /// Checks that `value` lies within `min..=max`; NaN is always rejected.
+    pub(crate) fn check(
+        register: Option<Register>,
+        field: &'static str,
+        value: f32,
+        min: f32,
+        max: f32,
+    ) -> Result<(), InvalidArgument> {
+        if (min..=max).contains(&value) {
+            Ok(())
+        } else {
+            Err(InvalidArgument {
+                register,
+                field,
+                value,
+                min,
+                max,
+            })
+        }
+    }
+}
+
+impl fmt::Display for InvalidArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(register) = self.register {
+            write!(f, "{register:?}.")?;
+        }
+        write!(
+            f,
+            "{} = {} is outside {}..={}",
+            self.field, self.value, self.min, self.max
+        )
+    }
+}
+
+/// Cause of a failed SPI link check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFault {
+    /// A test pattern written to the scratch register read back differently.
+    Readback {
+        /// Pattern that was written.
+        wrote: u32,
+        /// Value that was read back.
+        read: u32,
+    },
+    /// IOIN.VERSION did not read as 0x30.
+    Version(u8),
+    /// The SPI status byte had bits set that the TMC2160 always returns as 0, typically because
+    /// MISO is floating or shorted high.
+    StatusStuck(u8),
+}
+
+impl fmt::Display for LinkFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkFault::Readback { wrote, read } => {
+                write!(f, "wrote {wrote:#010x}, read back {read:#010x}")
+            }
+            LinkFault::Version(version) => write!(f, "version read as {version:#04x}"),
+            LinkFault::StatusStuck(status) => write!(f, "status byte stuck at {status:#04x}"),
+        }
+    }
 }
 
 /// Direction for motor rotation.
@@ -23,6 +269,15 @@ pub enum Direction {
     CCW,
 }
 
+/// STEP input mode, selected by CHOPCONF.dedge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SteppingMode {
+    /// A step is executed on each rising STEP edge; STEP is pulsed.
+    SingleEdge,
+    /// A step is executed on both STEP edges; STEP is toggled. This halves the required pulse rate.
+    DoubleEdge,
+}
+
 /// Microstepping resolution for the driver.
 /// These variants represent common microstepping modes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -52,28 +307,101 @@ impl MicrostepResolution {
     /// as required by the CHOPCONF register's MRES field.
     ///
     /// The mapping is based on the TMC2160 datasheet:
-    /// - 0: Full step (256 microsteps mode)
-    /// - 1: Half step (128 microsteps mode)
-    /// - 2: Quarter step (64 microsteps mode)
-    /// - 3: Eighth step (32 microsteps mode)
-    /// - 4: Sixteenth step (16 microsteps mode)
-    /// - 5: Thirty-second step (8 microsteps mode)
-    /// - 6: Sixty-fourth step (4 microsteps mode)
-    /// - 7: 1/128 microstep (2 microsteps mode)
-    /// - 8: 1/256 microstep (1 microstep mode)
-    ///
-    /// Adjust the mapping if necessary to match the datasheet.
+    /// - 0: 1/256 microstep (256 microsteps per full step)
+    /// - 1: 1/128 microstep (128 microsteps per full step)
+    /// - 2: Sixty-fourth step (64 microsteps per full step)
+    /// - 3: Thirty-second step (32 microsteps per full step)
+    /// - 4: Sixteenth step (16 microsteps per full step)
+    /// - 5: Eighth step (8 microsteps per full step)
+    /// - 6: Quarter step (4 microsteps per full step)
+    /// - 7: Half step (2 microsteps per full step)
+    /// - 8: Full step (1 microstep per full step)
     pub fn to_bits(self) -> u8 {
         match self {
-            MicrostepResolution::Full => 0,
-            MicrostepResolution::Half => 1,
-            MicrostepResolution::Quarter => 2,
-            MicrostepResolution::Eighth => 3,
+            MicrostepResolution::TwoFiftySixth => 0,
+            MicrostepResolution::OneTwentyEighth => 1,
+            MicrostepResolution::SixtyFourth => 2,
+            MicrostepResolution::ThirtySecond => 3,
             MicrostepResolution::Sixteenth => 4,
-            MicrostepResolution::ThirtySecond => 5,
-            MicrostepResolution::SixtyFourth => 6,
-            MicrostepResolution::OneTwentyEighth => 7,
-            MicrostepResolution::TwoFiftySixth => 8,
+            MicrostepResolution::Eighth => 5,
+            MicrostepResolution::Quarter => 6,
+            MicrostepResolution::Half => 7,
+            MicrostepResolution::Full => 8,
+        }
+    }
+
+    /// Decodes a CHOPCONF MRES field value. Values above 8 select full step mode on the device.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => MicrostepResolution::TwoFiftySixth,
+            1 => MicrostepResolution::OneTwentyEighth,
+            2 => MicrostepResolution::SixtyFourth,
+            3 => MicrostepResolution::ThirtySecond,
+            4 => MicrostepResolution::Sixteenth,
+            5 => MicrostepResolution::Eighth,
+            6 => MicrostepResolution::Quarter,
+            7 => MicrostepResolution::Half,
+            _ => MicrostepResolution::Full,
+        }
+    }
+
+    /// Returns the number of microsteps per full step.
+    pub fn microsteps(self) -> u16 {
+        256 >> self.to_bits()
+    }
+
+    /// Returns the distance covered by one step at this resolution, in 1/256 microsteps.
+    ///
+    /// This is the amount by which the internal microstep counter (MSCNT) advances per step.
+    pub fn step_size(self) -> u16 {
+        1 << self.to_bits()
+    }
+}
+
+/// Overtemperature shutdown threshold (DRV_CONF.OTSELECT).
+///
+/// The overtemperature prewarning (otpw) threshold of 120°C is not affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OtSelect {
+    /// Shutdown at 150°C (power-on default).
+    #[default]
+    Celsius150,
+    /// Shutdown at 143°C.
+    Celsius143,
+    /// Shutdown at 136°C (not recommended when VSA > 24V).
+    Celsius136,
+    /// Shutdown at 120°C (not recommended, no prewarning before shutdown).
+    Celsius120,
+}
+
+impl OtSelect {
+    /// Returns the OTSELECT field value.
+    pub fn to_bits(self) -> u8 {
+        match self {
+            OtSelect::Celsius150 => 0,
+            OtSelect::Celsius143 => 1,
+            OtSelect::Celsius136 => 2,
+            OtSelect::Celsius120 => 3,
+        }
+    }
+
+    /// Decodes an OTSELECT field value.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0x3 {
+            0 => OtSelect::Celsius150,
+            1 => OtSelect::Celsius143,
+            2 => OtSelect::Celsius136,
+            _ => OtSelect::Celsius120,
+        }
+    }
+
+    /// Returns the shutdown temperature in °C.
+    pub fn celsius(self) -> u8 {
+        match self {
+            OtSelect::Celsius150 => 150,
+            OtSelect::Celsius143 => 143,
+            OtSelect::Celsius136 => 136,
+            OtSelect::Celsius120 => 120,
         }
     }
 }
@@ -104,10 +432,91 @@ pub struct DriverStatus {
     pub cs_actual: u8,
 }
 
+/// Timing requirements of the STEP/DIR interface.
+///
+/// The defaults are the minimums from the TMC2160 datasheet (with the internal 12 MHz clock).
+/// Increase them for long cables or optocoupled inputs, which slow down the signal edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepTiming {
+    /// Minimum STEP high time (tSH) in nanoseconds.
+    pub min_high_ns: u32,
+    /// Minimum STEP low time (tSL) in nanoseconds.
+    pub min_low_ns: u32,
+    /// Minimum DIR to STEP setup time (tDSU) in nanoseconds.
+    pub dir_setup_ns: u32,
+    /// Minimum STEP to DIR hold time (tDSH) in nanoseconds.
+    pub dir_hold_ns: u32,
+}
+
+impl Default for StepTiming {
+    fn default() -> Self {
+        Self {
+            min_high_ns: 100,
+            min_low_ns: 100,
+            dir_setup_ns: 20,
+            dir_hold_ns: 20,
+        }
+    }
+}
+
+/// Backlash compensation settings.
+///
+/// When the direction of travel reverses, `microsteps` extra steps are issued before the next
+/// step to take up the mechanical play. These steps are not counted in the tracked position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backlash {
+    /// Backlash in steps of the current microstep resolution (0 disables compensation).
+    pub microsteps: u32,
+    /// Interval between compensation steps in nanoseconds. Choose a rate the motor can follow
+    /// from standstill.
+    pub step_interval_ns: u32,
+}
+
+impl Default for Backlash {
+    fn default() -> Self {
+        Self {
+            microsteps: 0,
+            step_interval_ns: 1_000_000,
+        }
+    }
+}
+
+/// DcStep settings, applied with `Tmc2160::configure_dcstep`.
+///
+/// DcStep is active while the DCEN input is high and the motor turns faster than the minimum
+/// velocity. Under load, the motor slows down instead of losing steps, down to the minimum velocity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcStepConfig {
+    /// Minimum DcStep velocity in revolutions per minute.
+    pub min_rpm: f32,
+    /// Full steps per motor revolution (200 for a 1.8° motor).
+    pub full_steps_per_rev: u16,
+    /// Upper PWM on time limit for commutation (DC_TIME) in clock cycles. Must exceed the blank
+    /// time configured in CHOPCONF.TBL.
+    pub dc_time: u16,
+    /// Maximum PWM on time for step loss detection (DC_SG) in multiples of 16 clock cycles;
+    /// 0 disables step loss detection. Set slightly above `dc_time / 16`.
+    pub dc_sg: u8,
+    /// Clock frequency of the TMC2160 in Hz (12 MHz when using the internal clock).
+    pub clock_hz: u32,
+}
+
+impl Default for DcStepConfig {
+    fn default() -> Self {
+        Self {
+            min_rpm: 30.0,
+            full_steps_per_rev: 200,
+            dc_time: 40,
+            dc_sg: 3,
+            clock_hz: 12_000_000,
+        }
+    }
+}
+
 /// Cache for storing write‑only register values.
 /// This cache is required to ensure that read‑modify‑write operations
 /// use the last known values for registers that cannot be read back.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct RegisterCache {
     /// Cached value for the IHOLD_IRUN register.
     pub ihold_irun: u32,
@@ -117,5 +526,56 @@ pub struct RegisterCache {
     pub coolconf: u32,
     /// Cached value for the PWMCONF register.
     pub pwmconf: u32,
+    /// Cached value for the GLOBAL_SCALER register.
+    pub global_scaler: u32,
+    /// Cached value for the VDCMIN register.
+    pub vdcmin: u32,
+    /// Cached value for the DCCTRL register.
+    pub dcctrl: u32,
+    /// Cached value for the SHORT_CONF register.
+    pub shortconf: u32,
+    /// Cached value for the DRV_CONF register.
+    pub drvconf: u32,
     // Add additional registers here as needed.
 }
+
+/// Power-on value of SHORT_CONF (S2VS_LEVEL = 6, S2G_LEVEL = 6, SHORTFILTER = 1).
+const SHORT_CONF_RESET: u32 = 0x0001_0606;
+
+/// Power-on value of DRV_CONF (BBMCLKS = 4, DRVSTRENGTH = 2).
+const DRV_CONF_RESET: u32 = 0x0008_0400;
+
+impl Default for RegisterCache {
+    /// Returns an empty cache. SHORT_CONF and DRV_CONF start at their power-on values, so their
+    /// protection and gate driver settings are kept when a single field is changed.
+    fn default() -> Self {
+        Self {
+            ihold_irun: 0,
+            tpwmthrs: 0,
+            coolconf: 0,
+            pwmconf: 0,
+            global_scaler: 0,
+            vdcmin: 0,
+            dcctrl: 0,
+            shortconf: SHORT_CONF_RESET,
+            drvconf: DRV_CONF_RESET,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn short_display_names_flagged_coils() {
+        let short = |coil_a, coil_b| Error::<(), ()>::ShortToGround { coil_a, coil_b }.to_string();
+        assert_eq!(short(true, true), "short to ground on coils A and B");
+        assert_eq!(short(true, false), "short to ground on coil A");
+        assert_eq!(short(false, true), "short to ground on coil B");
+        assert_eq!(short(false, false), "short to ground on no coil");
+    }
+}
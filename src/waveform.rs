@@ -0,0 +1,517 @@
+//! Custom microstep waveforms (MSLUT).
+//!
+//! The TMC2160 sequencer derives both coil currents from a quarter-wave table of 256 entries,
+//! mirrored to a full electrical cycle of 1024 microsteps. The table is stored differentially:
+//!
+//! - START_SIN (MSLUTSTART) is the value of entry 0, START_SIN90 the value of entry 256, which is
+//!   also the start value of coil B.
+//! - Bit `i` of MSLUT[0..7] (bit 0 of MSLUT[0] first) gives the difference between entry `i` and
+//!   entry `i + 1`: `W - 1` for a 0 bit and `W` for a 1 bit.
+//! - The table is divided into up to four segments starting at 0, X1, X2 and X3 (MSLUTSEL), each
+//!   with its own step width control `W` (W0..W3, 0..=3). Within a segment, consecutive entries
+//!   may therefore only differ by two adjacent values in -1..=3.
+//!
+//! `MicrostepTable::encode` takes a quarter wave of 256 samples and finds a segmentation with a
+//! greedy scan (each segment is extended as long as its differences fit one step width, which
+//! needs the fewest segments); boundaries between segments are then aligned to MSLUT words where
+//! the differences allow it. The quarter wave is taken to be flat at its end, i.e. entry 256
+//! equals the last sample. START_SIN90 defaults to the last sample as well, or is given with
+//! `MicrostepTable::encode_with_sin90`. `MicrostepTable::decode` reverses the encoding, so any table
+//! can be checked on the host before it is written with `Tmc2160::write_microstep_table`.
+//!
+//! `Waveform` describes parametric curves for cogging correction: a sine with third-harmonic
+//! content and a sine blended with a trapezoid. `Waveform::fit_error` compares an encoded table
+//! with the requested curve.
+
+use crate::registers::{MSLutSel, MSLutStart};
+
+/// Number of entries in the quarter-wave table.
+pub const TABLE_LEN: usize = 256;
+
+/// Errors while encoding or decoding a microstep table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveformError {
+    /// The difference between sample `index` and the next one is outside -1..=3.
+    StepOutOfRange {
+        /// Index of the sample before the step.
+        index: usize,
+    },
+    /// The differences cannot be covered by four segments.
+    TooManySegments,
+    /// A sample is outside 0..=255.
+    SampleOutOfRange {
+        /// Index of the sample.
+        index: usize,
+    },
+    /// A waveform parameter is outside its valid range.
+    InvalidParameter,
+}
+
+/// Parametric quarter-wave curves for cogging correction.
+///
+/// Curves are scaled so that their peak within the quarter wave equals the requested amplitude.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// A pure sine, `sin(x)`.
+    Sine,
+    /// A sine with third-harmonic content, `sin(x) + third * sin(3x)`.
+    ///
+    /// Negative values flatten the peak, positive values sharpen it. Below -1/3 the curve starts
+    /// negative and cannot be encoded.
+    ThirdHarmonic {
+        /// Relative amplitude of the third harmonic.
+        third: f32,
+    },
+    /// A sine blended with a trapezoid, `(1 - blend) * sin(x) + blend * trapezoid(x)`.
+    ///
+    /// The trapezoid rises linearly from 0 and reaches 1 after the fraction `ramp` of the quarter
+    /// wave. Steep ramps may exceed the maximum step of 3 between table entries.
+    TrapezoidBlend {
+        /// Share of the trapezoid, 0.0..=1.0.
+        blend: f32,
+        /// Fraction of the quarter wave spent on the rising edge, in (0.0, 1.0].
+        ramp: f32,
+    },
+}
+
+impl Waveform {
+    /// Returns the unscaled curve at the electrical angle `x` in radians (0..=π/2).
+    pub fn value(&self, x: f32) -> f32 {
+        match *self {
+            Waveform::Sine => libm::sinf(x),
+            Waveform::ThirdHarmonic { third } => libm::sinf(x) + third * libm::sinf(3.0 * x),
+            Waveform::TrapezoidBlend { blend, ramp } => {
+                let trapezoid = (x / (ramp * core::f32::consts::FRAC_PI_2)).min(1.0);
+                (1.0 - blend) * libm::sinf(x) + blend * trapezoid
+            }
+        }
+    }
+
+    /// Returns the ideal (unquantized) sample values for a peak of `amplitude`.
+    pub fn ideal(&self, amplitude: u8) -> Result<[f32; TABLE_LEN], WaveformError> {
+        let valid = match *self {
+            Waveform::Sine => true,
+            Waveform::ThirdHarmonic { third } => third.is_finite(),
+            Waveform::TrapezoidBlend { blend, ramp } => {
+                (0.0..=1.0).contains(&blend) && ramp > 0.0 && ramp <= 1.0
+            }
+        };
+        if !valid {
+            return Err(WaveformError::InvalidParameter);
+        }
+        // The peak is searched including entry 256, the end of the quarter wave.
+        let peak = (0..=TABLE_LEN)
+            .map(|i| self.value(entry_angle(i)))
+            .fold(0.0f32, f32::max);
+        if peak <= 0.0 {
+            return Err(WaveformError::InvalidParameter);
+        }
+        let scale = amplitude as f32 / peak;
+        let mut ideal = [0.0f32; TABLE_LEN];
+        for (i, value) in ideal.iter_mut().enumerate() {
+            *value = self.value(entry_angle(i)) * scale;
+        }
+        Ok(ideal)
+    }
+
+    /// Returns the quantized samples for a peak of `amplitude`.
+    pub fn samples(&self, amplitude: u8) -> Result<[u8; TABLE_LEN], WaveformError> {
+        let ideal = self.ideal(amplitude)?;
+        let mut samples = [0u8; TABLE_LEN];
+        for (index, (sample, value)) in samples.iter_mut().zip(ideal).enumerate() {
+            let value = libm::roundf(value);
+            if !(0.0..=255.0).contains(&value) {
+                return Err(WaveformError::SampleOutOfRange { index });
+            }
+            *sample = value as u8;
+        }
+        Ok(samples)
+    }
+
+    /// Encodes the curve for a peak of `amplitude`.
+    ///
+    /// Fails if the curve cannot be represented, e.g. because a step between entries exceeds the
+    /// range of a segment.
+    pub fn encode(&self, amplitude: u8) -> Result<MicrostepTable, WaveformError> {
+        MicrostepTable::encode(&self.samples(amplitude)?)
+    }
+
+    /// Compares an encoded table with the curve for a peak of `amplitude`.
+    pub fn fit_error(
+        &self,
+        table: &MicrostepTable,
+        amplitude: u8,
+    ) -> Result<FitError, WaveformError> {
+        let ideal = self.ideal(amplitude)?;
+        let decoded = table.decode()?;
+        let mut max_abs = 0.0f32;
+        let mut sum_sq = 0.0f32;
+        for (&sample, value) in decoded.iter().zip(ideal) {
+            let error = sample as f32 - value;
+            max_abs = max_abs.max(libm::fabsf(error));
+            sum_sq += error * error;
+        }
+        Ok(FitError {
+            max_abs,
+            rms: libm::sqrtf(sum_sq / TABLE_LEN as f32),
+        })
+    }
+}
+
+/// Deviation of an encoded table from the requested curve, in table units (the default table
+/// peaks at 248).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitError {
+    /// Largest absolute deviation of any entry.
+    pub max_abs: f32,
+    /// Root mean square deviation over all entries.
+    pub rms: f32,
+}
+
+/// Register values describing a microstep waveform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MicrostepTable {
+    /// MSLUT[0..7] register values.
+    lut: [u32; 8],
+    /// MSLUTSEL register value.
+    sel: u32,
+    /// MSLUTSTART register value.
+    start: u32,
+}
+
+impl MicrostepTable {
+    /// Creates a table from raw MSLUT[0..7], MSLUTSEL and MSLUTSTART values.
+    pub fn from_registers(lut: [u32; 8], sel: MSLutSel, start: MSLutStart) -> Self {
+        Self {
+            lut,
+            sel: sel.0,
+            start: start.0,
+        }
+    }
+
+    /// Encodes a quarter wave of 256 samples, with START_SIN90 equal to the last sample.
+    pub fn encode(samples: &[u8; TABLE_LEN]) -> Result<Self, WaveformError> {
+        Self::encode_with_sin90(samples, samples[TABLE_LEN - 1])
+    }
+
+    /// Encodes a quarter wave of 256 samples with the given START_SIN90, the start value of
+    /// coil B.
+    ///
+    /// The power-on table, for example, uses a START_SIN90 of 247 below its last sample of 248;
+    /// encoding its samples with that value reproduces it exactly.
+    pub fn encode_with_sin90(
+        samples: &[u8; TABLE_LEN],
+        start_sin90: u8,
+    ) -> Result<Self, WaveformError> {
+        let mut deltas = [0i16; TABLE_LEN];
+        for (i, delta) in deltas.iter_mut().enumerate().take(TABLE_LEN - 1) {
+            *delta = samples[i + 1] as i16 - samples[i] as i16;
+        }
+        // deltas[255] stays 0: the quarter wave is flat at its end.
+
+        let mut starts = [TABLE_LEN - 1; 4];
+        starts[0] = 0;
+        let mut segment = 0;
+        let (mut lo, mut hi) = (deltas[0], deltas[0]);
+        for (index, &delta) in deltas.iter().enumerate() {
+            if !(-1..=3).contains(&delta) {
+                return Err(WaveformError::StepOutOfRange { index });
+            }
+            if delta.max(hi) - delta.min(lo) > 1 {
+                segment += 1;
+                if segment == starts.len() {
+                    return Err(WaveformError::TooManySegments);
+                }
+                starts[segment] = index;
+                (lo, hi) = (delta, delta);
+            } else {
+                (lo, hi) = (delta.min(lo), delta.max(hi));
+            }
+        }
+        // A run of differences that fits both segments may go to either. Place the boundary at the
+        // first MSLUT word boundary within the run, so each register covers a single segment where
+        // possible, as in the power-on table; otherwise at the start of the run.
+        for seg in 1..=segment {
+            let end = if seg < segment {
+                starts[seg + 1]
+            } else {
+                TABLE_LEN
+            };
+            let (mut lo, mut hi) = delta_range(&deltas[starts[seg]..end]);
+            let latest = starts[seg];
+            while starts[seg] > starts[seg - 1] + 1 {
+                let delta = deltas[starts[seg] - 1];
+                if delta.max(hi) - delta.min(lo) > 1 {
+                    break;
+                }
+                (lo, hi) = (delta.min(lo), delta.max(hi));
+                starts[seg] -= 1;
+            }
+            let aligned = starts[seg].next_multiple_of(32);
+            if aligned <= latest {
+                starts[seg] = aligned;
+            }
+        }
+        let mut widths = [0i16; 4];
+        for seg in 0..=segment {
+            let end = if seg < segment {
+                starts[seg + 1]
+            } else {
+                TABLE_LEN
+            };
+            widths[seg] = step_width(delta_range(&deltas[starts[seg]..end]).1);
+        }
+        // Unused segments repeat the last one and start at the final entry.
+        let last = widths[segment];
+        widths[segment + 1..].fill(last);
+
+        let mut sel = MSLutSel(0);
+        sel.set_w0(widths[0] as u32);
+        sel.set_w1(widths[1] as u32);
+        sel.set_w2(widths[2] as u32);
+        sel.set_w3(widths[3] as u32);
+        sel.set_x1(starts[1] as u32);
+        sel.set_x2(starts[2] as u32);
+        sel.set_x3(starts[3] as u32);
+
+        let mut table = Self {
+            lut: [0; 8],
+            sel: sel.0,
+            start: 0,
+        };
+        for (i, &delta) in deltas.iter().enumerate() {
+            if delta == table.step_width_at(i) {
+                table.lut[i / 32] |= 1 << (i % 32);
+            }
+        }
+        let mut start = MSLutStart(0);
+        start.set_start_sin(samples[0] as u32);
+        start.set_start_sin90(start_sin90 as u32);
+        table.start = start.0;
+        Ok(table)
+    }
+
+    /// Encodes a sine quarter wave with the given peak value, see `sine_samples`.
+    pub fn sine(amplitude: u8) -> Result<Self, WaveformError> {
+        Self::encode(&sine_samples(amplitude))
+    }
+
+    /// Decodes the table back into a quarter wave of 256 samples.
+    pub fn decode(&self) -> Result<[u8; TABLE_LEN], WaveformError> {
+        let mut samples = [0u8; TABLE_LEN];
+        let mut value = self.start().start_sin() as i16;
+        samples[0] = value as u8;
+        for (index, sample) in samples.iter_mut().enumerate().skip(1) {
+            let i = index - 1;
+            let bit = (self.lut[i / 32] >> (i % 32)) & 1;
+            value += self.step_width_at(i) - 1 + bit as i16;
+            if !(0..=255).contains(&value) {
+                return Err(WaveformError::SampleOutOfRange { index });
+            }
+            *sample = value as u8;
+        }
+        Ok(samples)
+    }
+
+    /// Returns the MSLUT[0..7] register values.
+    pub fn lut(&self) -> [u32; 8] {
+        self.lut
+    }
+
+    /// Returns the MSLUTSEL register value.
+    pub fn sel(&self) -> MSLutSel {
+        MSLutSel(self.sel)
+    }
+
+    /// Returns the MSLUTSTART register value.
+    pub fn start(&self) -> MSLutStart {
+        MSLutStart(self.start)
+    }
+
+    /// Returns the step width control W of the segment containing entry `i`.
+    fn step_width_at(&self, i: usize) -> i16 {
+        let sel = self.sel();
+        let i = i as u32;
+        let width = if i >= sel.x3() {
+            sel.w3()
+        } else if i >= sel.x2() {
+            sel.w2()
+        } else if i >= sel.x1() {
+            sel.w1()
+        } else {
+            sel.w0()
+        };
+        width as i16
+    }
+}
+
+/// The power-on default table of the TMC2160, a sine wave with a peak of 248.
+impl Default for MicrostepTable {
+    fn default() -> Self {
+        Self {
+            lut: [
+                0xAAAA_B554,
+                0x4A95_54AA,
+                0x2449_2929,
+                0x1010_4222,
+                0xFBFF_FFFF,
+                0xB5BB_777D,
+                0x4929_5556,
+                0x0040_4222,
+            ],
+            sel: 0xFFFF_8056,
+            start: 0x00F7_0000,
+        }
+    }
+}
+
+/// Returns a sine quarter wave: sample `i` is `amplitude * sin(2π * i / 1024)`, rounded.
+///
+/// Amplitudes up to 248 leave headroom for the chopper; the TMC2160 default table uses 248.
+pub fn sine_samples(amplitude: u8) -> [u8; TABLE_LEN] {
+    let mut samples = [0u8; TABLE_LEN];
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample = libm::roundf(amplitude as f32 * libm::sinf(entry_angle(i))) as u8;
+    }
+    samples
+}
+
+/// Returns the smallest and largest of the differences.
+fn delta_range(deltas: &[i16]) -> (i16, i16) {
+    deltas
+        .iter()
+        .fold((i16::MAX, i16::MIN), |(lo, hi), &delta| {
+            (delta.min(lo), delta.max(hi))
+        })
+}
+
+/// Returns the electrical angle of table entry `i` in radians.
+fn entry_angle(i: usize) -> f32 {
+    2.0 * core::f32::consts::PI * i as f32 / 1024.0
+}
+
+/// Returns the step width control W for a segment with differences in `hi - 1..=hi`.
+fn step_width(hi: i16) -> i16 {
+    // A 0 bit adds W - 1 and a 1 bit adds W. W = hi covers both hi - 1 and hi, except for a
+    // segment of -1 steps only, which needs W = 0.
+    hi.max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_round_trip() {
+        for amplitude in [0, 1, 64, 128, 200, 248, 255] {
+            let samples = sine_samples(amplitude);
+            let table = MicrostepTable::sine(amplitude).unwrap();
+            assert_eq!(table.decode().unwrap(), samples, "amplitude {amplitude}");
+            assert_eq!(table.start().start_sin(), 0);
+            assert_eq!(table.start().start_sin90(), samples[TABLE_LEN - 1] as u32);
+        }
+        let table = MicrostepTable::sine(248).unwrap();
+        assert_eq!(Waveform::Sine.encode(248), Ok(table));
+        let fit = Waveform::Sine.fit_error(&table, 248).unwrap();
+        assert!(fit.max_abs <= 0.5 && fit.rms < 0.3, "{fit:?}");
+    }
+
+    #[test]
+    fn default_is_power_on_table() {
+        let table = MicrostepTable::default();
+        // MSLUT[0..7], MSLUTSEL and MSLUTSTART reset values from the datasheet.
+        assert_eq!(
+            table.lut(),
+            [
+                0xAAAA_B554,
+                0x4A95_54AA,
+                0x2449_2929,
+                0x1010_4222,
+                0xFBFF_FFFF,
+                0xB5BB_777D,
+                0x4929_5556,
+                0x0040_4222,
+            ]
+        );
+        assert_eq!(table.sel().0, 0xFFFF_8056);
+        assert_eq!(table.start().0, 0x00F7_0000);
+        let sel = table.sel();
+        assert_eq!((sel.w0(), sel.w1(), sel.w2(), sel.w3()), (2, 1, 1, 1));
+        assert_eq!((sel.x1(), sel.x2(), sel.x3()), (128, 255, 255));
+
+        // The power-on table is a sine with a peak of 248, within one unit of `sine_samples`.
+        let samples = table.decode().unwrap();
+        let sine = sine_samples(248);
+        for (i, (&sample, &expected)) in samples.iter().zip(sine.iter()).enumerate() {
+            assert!(
+                sample.abs_diff(expected) <= 1,
+                "entry {i}: {sample} vs {expected}"
+            );
+        }
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(samples[TABLE_LEN - 1], 248);
+        let reencoded = MicrostepTable::encode(&samples).unwrap();
+        assert_eq!(reencoded.decode().unwrap(), samples);
+        assert_eq!(reencoded.start().start_sin90(), 248);
+        assert_eq!(MicrostepTable::encode_with_sin90(&samples, 247), Ok(table));
+    }
+
+    #[test]
+    fn encode_errors() {
+        for waveform in [
+            Waveform::ThirdHarmonic { third: f32::NAN },
+            Waveform::TrapezoidBlend {
+                blend: 1.5,
+                ramp: 0.5,
+            },
+            Waveform::TrapezoidBlend {
+                blend: 0.5,
+                ramp: 0.0,
+            },
+        ] {
+            assert_eq!(
+                waveform.encode(248),
+                Err(WaveformError::InvalidParameter),
+                "{waveform:?}"
+            );
+        }
+        // Below -1/3 the curve starts negative.
+        assert_eq!(
+            Waveform::ThirdHarmonic { third: -0.4 }.encode(248),
+            Err(WaveformError::SampleOutOfRange { index: 3 })
+        );
+        // A steep trapezoid rises by more than 3 per entry.
+        assert_eq!(
+            Waveform::TrapezoidBlend {
+                blend: 1.0,
+                ramp: 0.1,
+            }
+            .encode(248),
+            Err(WaveformError::StepOutOfRange { index: 0 })
+        );
+        // Steps alternating between 0 and 2 need a new segment at every entry.
+        let mut samples = [0u8; TABLE_LEN];
+        for i in 1..TABLE_LEN {
+            samples[i] = samples[i - 1] + if i % 2 == 0 { 2 } else { 0 };
+        }
+        assert_eq!(
+            MicrostepTable::encode(&samples),
+            Err(WaveformError::TooManySegments)
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut sel = MSLutSel(0);
+        sel.set_w0(3);
+        sel.set_x1(255);
+        sel.set_x2(255);
+        sel.set_x3(255);
+        let table = MicrostepTable::from_registers([u32::MAX; 8], sel, MSLutStart(0));
+        // Steps of 3 exceed 255 after 85 entries.
+        assert_eq!(
+            table.decode(),
+            Err(WaveformError::SampleOutOfRange { index: 86 })
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Commissioning test of the motor wiring.
+//!
+//! `Tmc2160::wiring_self_test` checks the motor connection before motion is enabled. It runs three
+//! phases at a low current, with SpreadCycle forced since the open load detection does not work in
+//! StealthChop:
+//!
+//! 1. Coil A alone is energized through XDIRECT, reversing its polarity a few times.
+//! 2. The same for coil B.
+//! 3. Both coils are driven by the sequencer for a few microsteps.
+//!
+//! After each phase, DRV_STATUS is read for the open load (ola/olb), short to ground (s2ga/s2gb)
+//! and short to supply (s2vsa/s2vsb) flags; the short detection thresholds are those set with
+//! `Tmc2160::set_short_conf`. The flags are combined into a `WiringReport`:
+//!
+//! - A short of any phase is reported as such, and the test stops there.
+//! - If both coils show an open load when driven alone, but not when driven together, the two
+//!   coils are cross-wired: each bridge is connected to one end of both windings, so current only
+//!   flows when both bridges drive.
+//! - Otherwise a coil with an open load is reported as missing.
+//!
+//! Open load is a hint rather than a measurement: it is flagged when the chopper cannot reach the
+//! target current, so a high-impedance motor at a high speed or a low supply voltage may also show
+//! it. Use a low `WiringTest::current` and a slow step rate.
+
+use crate::registers::DrvStatus;
+
+/// Parameters of a wiring self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WiringTest {
+    /// Run and hold current (0..=31) used during the test.
+    pub current: u8,
+    /// Number of polarity reversals per coil in the single-coil phases (at least 2).
+    pub reversals: u8,
+    /// Time each polarity is held in microseconds.
+    pub dwell_us: u32,
+    /// Number of microsteps moved with both coils driven.
+    pub steps: u16,
+    /// Time between these microsteps in microseconds.
+    pub step_interval_us: u32,
+}
+
+impl Default for WiringTest {
+    /// Tests at current scale 8 with four reversals of 5 ms and 16 microsteps at 1 ms.
+    fn default() -> Self {
+        Self {
+            current: 8,
+            reversals: 4,
+            dwell_us: 5_000,
+            steps: 16,
+            step_interval_us: 1_000,
+        }
+    }
+}
+
+/// Result of a wiring self-test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WiringReport {
+    /// Both coils are connected and no short was detected.
+    Ok,
+    /// No current flows through the flagged coils; both flagged means no motor is connected.
+    MissingCoil {
+        /// Coil A is open.
+        coil_a: bool,
+        /// Coil B is open.
+        coil_b: bool,
+    },
+    /// The wires of coil A and coil B are cross-connected.
+    SwappedPhase,
+    /// A short to ground was detected.
+    ShortToGround {
+        /// Short on coil A.
+        coil_a: bool,
+        /// Short on coil B.
+        coil_b: bool,
+    },
+    /// A short to the supply voltage was detected.
+    ShortToSupply {
+        /// Short on coil A.
+        coil_a: bool,
+        /// Short on coil B.
+        coil_b: bool,
+    },
+}
+
+impl WiringReport {
+    /// Returns `true` if the wiring is OK.
+    pub fn is_ok(&self) -> bool {
+        *self == WiringReport::Ok
+    }
+
+    /// Returns the short reported in DRV_STATUS, if any.
+    pub(crate) fn from_shorts(status: DrvStatus) -> Option<WiringReport> {
+        if status.s2ga() || status.s2gb() {
+            Some(WiringReport::ShortToGround {
+                coil_a: status.s2ga(),
+                coil_b: status.s2gb(),
+            })
+        } else if status.s2vsa() || status.s2vsb() {
+            Some(WiringReport::ShortToSupply {
+                coil_a: status.s2vsa(),
+                coil_b: status.s2vsb(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Classifies the open load flags (coil A, coil B) seen with coil A alone, coil B alone, and
+    /// both coils driven.
+    pub(crate) fn from_open_load(
+        single_a: bool,
+        single_b: bool,
+        combined: (bool, bool),
+    ) -> WiringReport {
+        let combined_open = combined.0 || combined.1;
+        if single_a && single_b && !combined_open {
+            WiringReport::SwappedPhase
+        } else if single_a || single_b {
+            WiringReport::MissingCoil {
+                coil_a: single_a,
+                coil_b: single_b,
+            }
+        } else if combined_open {
+            WiringReport::MissingCoil {
+                coil_a: combined.0,
+                coil_b: combined.1,
+            }
+        } else {
+            WiringReport::Ok
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S2VSA: u32 = 1 << 12;
+    const S2VSB: u32 = 1 << 13;
+    const S2GA: u32 = 1 << 27;
+    const S2GB: u32 = 1 << 28;
+    const OLA: u32 = 1 << 29;
+
+    #[test]
+    fn classifies_shorts() {
+        let cases = [
+            (0, None),
+            (OLA, None),
+            (
+                S2GA | S2VSB,
+                Some(WiringReport::ShortToGround {
+                    coil_a: true,
+                    coil_b: false,
+                }),
+            ),
+            (
+                S2GA | S2GB,
+                Some(WiringReport::ShortToGround {
+                    coil_a: true,
+                    coil_b: true,
+                }),
+            ),
+            (
+                S2VSB,
+                Some(WiringReport::ShortToSupply {
+                    coil_a: false,
+                    coil_b: true,
+                }),
+            ),
+            (
+                S2VSA | OLA,
+                Some(WiringReport::ShortToSupply {
+                    coil_a: true,
+                    coil_b: false,
+                }),
+            ),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(
+                WiringReport::from_shorts(DrvStatus(status)),
+                expected,
+                "DRV_STATUS {status:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn classifies_open_load() {
+        let missing = |coil_a, coil_b| WiringReport::MissingCoil { coil_a, coil_b };
+        let cases = [
+            (false, false, (false, false), WiringReport::Ok),
+            (true, true, (false, false), WiringReport::SwappedPhase),
+            (true, true, (true, true), missing(true, true)),
+            (true, true, (true, false), missing(true, true)),
+            (true, false, (false, false), missing(true, false)),
+            (false, true, (false, true), missing(false, true)),
+            (false, false, (true, false), missing(true, false)),
+            (false, false, (false, true), missing(false, true)),
+        ];
+        for (single_a, single_b, combined, expected) in cases {
+            assert_eq!(
+                WiringReport::from_open_load(single_a, single_b, combined),
+                expected,
+                "{single_a} {single_b} {combined:?}"
+            );
+        }
+    }
+}